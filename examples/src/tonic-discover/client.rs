@@ -36,7 +36,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     for i in 5..10 {
         let instance = naming_client.select_instance(param.clone()).await?;
         let mut client =
-            GreeterClient::connect(format!("http://{}:{}", &instance.ip, &instance.port)).await?;
+            GreeterClient::connect(format!("http://{}", instance.get_ip_port())).await?;
         let request = tonic::Request::new(HelloRequest {
             name: format!("Tonic {} [client by naming client select]", i),
         });
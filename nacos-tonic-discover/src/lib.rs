@@ -121,17 +121,19 @@ impl InnerTonicDiscover {
             let sender = entity.sender.clone();
             async move {
                 for item in &add_list {
-                    let host = format!("{}:{}", &item.ip, item.port);
+                    let host = item.get_ip_port();
                     match Endpoint::from_str(&format!("http://{}", host)) {
                         Ok(endpoint) => {
                             let change = Change::Insert(host, endpoint);
                             sender.send(change).await.unwrap_or_default();
                         }
-                        Err(_) => todo!(),
+                        Err(e) => {
+                            log::warn!("build endpoint error,{},{}", &host, e);
+                        }
                     };
                 }
                 for item in &remove_list {
-                    let host = format!("{}:{}", &item.ip, item.port);
+                    let host = item.get_ip_port();
                     let change = Change::Remove(host);
                     sender.send(change).await.unwrap_or_default();
                 }
@@ -22,9 +22,9 @@ impl AuthActor {
             false
         };
         Self {
+            client: endpoints.http_client(),
             endpoints,
             auth: auth_info,
-            client: reqwest::Client::new(),
             use_auth,
             token: Default::default(),
             token_time_out: Default::default(),
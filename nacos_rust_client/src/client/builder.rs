@@ -1,11 +1,22 @@
-use std::{collections::HashMap, env, sync::Arc};
-
-use crate::{conn_manage::manage::ConnManage, init_global_system_actor};
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
+
+use crate::{
+    conn_manage::{
+        address_server::AddressServerConfig,
+        manage::ConnManage,
+        options::{ConnectionOptions, ProtocolMode},
+    },
+    init_global_system_actor,
+};
 
 use super::{
-    config_client::inner_client::ConfigInnerRequestClient, nacos_client::ActixSystemActorSetCmd,
-    naming_client::InnerNamingRequestClient, AuthInfo, ClientInfo, ConfigClient, HostInfo,
-    NamingClient, ServerEndpointInfo,
+    nacos_client::ActixSystemActorSetCmd,
+    naming_client::{
+        InstanceProtectConfig, InstanceReconcileConfig, NamingCacheConfig, NamingListenerOptions,
+        NamingUdpConfig,
+    },
+    AuthInfo, ClientInfo, ConfigClient, HostInfo, InetConfig, NacosConnection, NamingClient,
+    ServerEndpointInfo, TlsConfig,
 };
 
 #[derive(Clone, Debug)]
@@ -13,8 +24,14 @@ pub struct ClientBuilder {
     endpoint: ServerEndpointInfo,
     tenant: String,
     auth_info: Option<AuthInfo>,
-    use_grpc: bool,
+    protocol_mode: ProtocolMode,
     client_info: ClientInfo,
+    custom_client_ip: bool,
+    inet_config: Option<InetConfig>,
+    naming_options: NamingListenerOptions,
+    address_server: Option<AddressServerConfig>,
+    tls_config: Option<TlsConfig>,
+    connection_options: ConnectionOptions,
 }
 
 impl ClientBuilder {
@@ -24,8 +41,14 @@ impl ClientBuilder {
             endpoint,
             tenant: "public".to_owned(),
             auth_info: None,
-            use_grpc: true,
+            protocol_mode: ProtocolMode::Grpc,
             client_info: Default::default(),
+            custom_client_ip: false,
+            inet_config: None,
+            naming_options: Default::default(),
+            address_server: None,
+            tls_config: None,
+            connection_options: Default::default(),
         }
     }
 
@@ -45,7 +68,26 @@ impl ClientBuilder {
     }
 
     pub fn set_hosts(mut self, hosts: Vec<HostInfo>) -> Self {
-        self.endpoint = ServerEndpointInfo { hosts };
+        self.endpoint = ServerEndpointInfo::from_hosts(hosts);
+        self
+    }
+
+    /// 从地址服务器获取服务端地址列表,并按 refresh_interval 定时刷新;
+    /// url 不以 `http` 开头时按 `http://{url}/nacos/serverlist` 请求。设置后忽略 endpoint 中的地址。
+    pub fn set_address_server(mut self, url: &str, refresh_interval: Duration) -> Self {
+        self.address_server = Some(AddressServerConfig::new(url, refresh_interval));
+        self
+    }
+
+    /// 使用 TLS(grpc)与 https(http)连接服务端
+    pub fn set_tls_config(mut self, tls_config: TlsConfig) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+
+    /// 熔断、重连退避、请求超时与心跳间隔等连接配置
+    pub fn set_connection_options(mut self, connection_options: ConnectionOptions) -> Self {
+        self.connection_options = connection_options;
         self
     }
 
@@ -55,7 +97,17 @@ impl ClientBuilder {
     }
 
     pub fn set_use_grpc(mut self, use_grpc: bool) -> Self {
-        self.use_grpc = use_grpc;
+        self.protocol_mode = if use_grpc {
+            ProtocolMode::Grpc
+        } else {
+            ProtocolMode::Http
+        };
+        self
+    }
+
+    /// 设置协议模式;Auto 模式下按 ConnectionOptions 的 protocol_probe_interval 探测 grpc 是否可用
+    pub fn set_protocol_mode(mut self, protocol_mode: ProtocolMode) -> Self {
+        self.protocol_mode = protocol_mode;
         self
     }
 
@@ -66,6 +118,13 @@ impl ClientBuilder {
 
     pub fn set_client_ip(mut self, client_ip: String) -> Self {
         self.client_info.client_ip = client_ip;
+        self.custom_client_ip = true;
+        self
+    }
+
+    /// 多网卡时按规则选择注册IP;设置了 `NACOS_CLIENT_IP` 环境变量或 set_client_ip 时不生效
+    pub fn set_inet_config(mut self, inet_config: InetConfig) -> Self {
+        self.inet_config = Some(inet_config);
         self
     }
 
@@ -79,6 +138,29 @@ impl ClientBuilder {
         self
     }
 
+    pub fn set_instance_protect_config(mut self, config: InstanceProtectConfig) -> Self {
+        self.naming_options.protect_config = config;
+        self
+    }
+
+    /// 定期核对服务端注册实例的间隔,以及是否纠正与本地不一致的实例
+    pub fn set_instance_reconcile_config(mut self, config: InstanceReconcileConfig) -> Self {
+        self.naming_options.reconcile_config = config;
+        self
+    }
+
+    /// http 模式下接收服务端 udp 推送的配置
+    pub fn set_naming_udp_config(mut self, config: NamingUdpConfig) -> Self {
+        self.naming_options.udp_config = config;
+        self
+    }
+
+    /// 开启服务实例本地磁盘缓存,服务端不可用时使用缓存的实例列表
+    pub fn set_naming_cache_config(mut self, config: NamingCacheConfig) -> Self {
+        self.naming_options.cache_config = Some(config);
+        self
+    }
+
     pub fn build_config_client(self) -> Arc<ConfigClient> {
         let (config_client, _) = self.build();
         config_client
@@ -89,61 +171,68 @@ impl ClientBuilder {
         naming_client
     }
 
+    /// TLS 配置错误(证书文件不存在、私钥格式错误等)时 panic,需要处理错误时使用 try_build
     pub fn build(self) -> (Arc<ConfigClient>, Arc<NamingClient>) {
-        let use_grpc = self.use_grpc;
-        let auth_info = self.auth_info;
-        let endpoint = Arc::new(self.endpoint);
-        let namespace_id = self.tenant.clone();
-        let tenant = self.tenant;
+        self.try_build().expect("build nacos client error")
+    }
+
+    pub fn try_build(self) -> anyhow::Result<(Arc<ConfigClient>, Arc<NamingClient>)> {
+        let tenant = self.tenant.clone();
+        let connection = self.build_connection()?;
+        let naming_client = connection.naming_client(&tenant);
+        let system_addr = init_global_system_actor();
+        system_addr.do_send(ActixSystemActorSetCmd::LastNamingClient(
+            naming_client.clone(),
+        ));
+        let config_client = connection.config_client(&tenant);
+        system_addr.do_send(ActixSystemActorSetCmd::LastConfigClient(
+            config_client.clone(),
+        ));
+        Ok((config_client, naming_client))
+    }
+
+    /// 创建共享连接,由 NacosConnection 按命名空间创建客户端;忽略 set_tenant 的设置
+    pub fn build_connection(mut self) -> anyhow::Result<NacosConnection> {
+        if let Some(inet_config) = &self.inet_config {
+            if !self.custom_client_ip && env::var("NACOS_CLIENT_IP").is_err() {
+                self.client_info.client_ip = inet_config.select_ip();
+            }
+        }
+        //证书只在创建连接时加载一次,http 与 grpc 请求共享
+        let mut endpoint = self.endpoint.set_tls_config(self.tls_config)?;
+        endpoint.options = Arc::new(self.connection_options.clone());
+        //Auto 模式先使用 http 协议,由 ConnManage 探测后通知切换
+        let use_grpc = self.protocol_mode == ProtocolMode::Grpc;
+        let endpoint = Arc::new(endpoint);
         let current_ip = self.client_info.client_ip.clone();
 
+        //使用地址服务器时,由 ConnManage 启动后获取地址列表
+        let hosts = if self.address_server.is_some() {
+            vec![]
+        } else {
+            endpoint.hosts.clone()
+        };
         let conn_manage = ConnManage::new(
-            endpoint.hosts.clone(),
+            hosts,
             use_grpc,
-            auth_info.clone(),
+            self.auth_info.clone(),
             Default::default(),
             Arc::new(self.client_info),
-        );
+        )
+        .set_address_server(self.address_server)
+        .set_tls_client(endpoint.tls.clone())
+        .set_connection_options(self.connection_options)
+        .set_protocol_mode(self.protocol_mode);
+        let conn_state = conn_manage.subscribe_state();
         let conn_manage_addr = conn_manage.start_at_global_system();
-        let request_client = InnerNamingRequestClient::new_with_endpoint(endpoint.clone());
-        let addrs = NamingClient::init_register(
-            namespace_id.clone(),
-            current_ip.clone(),
-            request_client,
-            auth_info.clone(),
-            Some(conn_manage_addr.clone().downgrade()),
-            use_grpc,
-        );
-        let naming_client = Arc::new(NamingClient {
-            namespace_id,
-            register: addrs.0,
-            listener_addr: addrs.1,
+        Ok(NacosConnection::new(
+            endpoint,
+            self.auth_info,
             current_ip,
-            _conn_manage_addr: conn_manage_addr.clone(),
-        });
-        let system_addr = init_global_system_actor();
-        system_addr.do_send(ActixSystemActorSetCmd::LastNamingClient(
-            naming_client.clone(),
-        ));
-
-        let mut request_client = ConfigInnerRequestClient::new_with_endpoint(endpoint);
-        let (config_inner_addr, auth_addr) = ConfigClient::init_register(
-            request_client.clone(),
-            auth_info,
-            Some(conn_manage_addr.clone().downgrade()),
             use_grpc,
-        );
-        request_client.set_auth_addr(auth_addr);
-        let config_client = Arc::new(ConfigClient {
-            tenant,
-            request_client,
-            config_inner_addr,
-            conn_manage_addr: conn_manage_addr,
-        });
-        //let system_addr = init_global_system_actor();
-        system_addr.do_send(ActixSystemActorSetCmd::LastConfigClient(
-            config_client.clone(),
-        ));
-        (config_client, naming_client)
+            self.naming_options,
+            conn_manage_addr,
+            conn_state,
+        ))
     }
 }
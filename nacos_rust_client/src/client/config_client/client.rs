@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use actix::{Addr, WeakAddr};
+use tokio::sync::watch;
 
 use crate::{
     client::{
@@ -11,7 +12,8 @@ use crate::{
     },
     conn_manage::{
         conn_msg::{ConfigRequest, ConfigResponse},
-        manage::ConnManage,
+        conn_state::ConnState,
+        manage::{ConnManage, ConnManageCmd},
     },
     init_global_system_actor,
 };
@@ -28,6 +30,7 @@ pub struct ConfigClient {
     pub(crate) request_client: ConfigInnerRequestClient,
     pub(crate) config_inner_addr: Addr<ConfigInnerActor>,
     pub(crate) conn_manage_addr: Addr<ConnManage>,
+    pub(crate) conn_state: watch::Receiver<ConnState>,
 }
 
 impl Drop for ConfigClient {
@@ -48,6 +51,7 @@ impl ConfigClient {
             Default::default(),
             Default::default(),
         );
+        let conn_state = conn_manage.subscribe_state();
         let conn_manage_addr = conn_manage.start_at_global_system();
         let (config_inner_addr, _) = Self::init_register(
             request_client.clone(),
@@ -61,6 +65,7 @@ impl ConfigClient {
             request_client,
             config_inner_addr,
             conn_manage_addr: conn_manage_addr,
+            conn_state,
         });
         let system_addr = init_global_system_actor();
         system_addr.do_send(ActixSystemActorSetCmd::LastConfigClient(r.clone()));
@@ -77,6 +82,7 @@ impl ConfigClient {
             Default::default(),
             Default::default(),
         );
+        let conn_state = conn_manage.subscribe_state();
         let conn_manage_addr = conn_manage.start_at_global_system();
         let mut request_client = ConfigInnerRequestClient::new_with_endpoint(endpoint);
         let (config_inner_addr, auth_addr) = Self::init_register(
@@ -91,6 +97,7 @@ impl ConfigClient {
             request_client,
             config_inner_addr,
             conn_manage_addr: conn_manage_addr,
+            conn_state,
         });
         let system_addr = init_global_system_actor();
         system_addr.do_send(ActixSystemActorSetCmd::LastConfigClient(r.clone()));
@@ -154,6 +161,17 @@ impl ConfigClient {
         Ok(())
     }
 
+    /// 运行时更新服务端地址列表,当前连接的地址被移除时会切换连接并重新订阅
+    pub fn update_server_hosts(&self, hosts: Vec<HostInfo>) {
+        self.conn_manage_addr
+            .do_send(ConnManageCmd::UpdateHosts(hosts));
+    }
+
+    /// 订阅与服务端的连接状态,可用于就绪检查与告警
+    pub fn connection_state(&self) -> watch::Receiver<ConnState> {
+        self.conn_state.clone()
+    }
+
     /*
     pub(crate) async fn listene(&self,content:&str,timeout:Option<u64>) -> anyhow::Result<Vec<ConfigKey>> {
         self.request_client.listene(content, timeout).await
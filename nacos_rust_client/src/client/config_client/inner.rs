@@ -22,6 +22,8 @@ pub struct ConfigInnerActor {
     subscribe_map: HashMap<ConfigKey, ListenerValue>,
     conn_manage: Option<WeakAddr<ConnManage>>,
     use_grpc: bool,
+    //http 长轮询是否在运行,避免切换协议时重复启动
+    v1_listening: bool,
 }
 
 //type ConfigInnerHandleResultSender = tokio::sync::oneshot::Sender<ConfigInnerHandleResult>;
@@ -39,6 +41,7 @@ pub enum ConfigInnerCmd {
     Notify(Vec<NotifyConfigItem>),
     Close,
     GrpcResubscribe,
+    UpdateProtocol(bool),
 }
 
 pub enum ConfigInnerHandleResult {
@@ -57,6 +60,7 @@ impl ConfigInnerActor {
             subscribe_map: Default::default(),
             conn_manage,
             use_grpc,
+            v1_listening: false,
         }
     }
 
@@ -102,9 +106,12 @@ impl ConfigInnerActor {
 
     fn listener(&mut self, ctx: &mut actix::Context<Self>) {
         if self.use_grpc {
+            self.v1_listening = false;
             return;
         }
+        self.v1_listening = false;
         if let Some(content) = self.get_listener_body() {
+            self.v1_listening = true;
             let conn_manage = self.conn_manage.clone();
             async move {
                 let mut list = vec![];
@@ -147,6 +154,8 @@ impl ConfigInnerActor {
                     ctx.run_later(Duration::from_millis(5), |act, ctx| {
                         act.listener(ctx);
                     });
+                } else {
+                    this.v1_listening = false;
                 }
             })
             .spawn(ctx);
@@ -192,7 +201,6 @@ impl Handler<ConfigInnerCmd> for ConfigInnerActor {
     fn handle(&mut self, msg: ConfigInnerCmd, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
             ConfigInnerCmd::SUBSCRIBE(key, id, md5, func) => {
-                let first = self.subscribe_map.len() == 0;
                 let list = self.subscribe_map.get_mut(&key);
                 match list {
                     Some(v) => {
@@ -216,7 +224,8 @@ impl Handler<ConfigInnerCmd> for ConfigInnerActor {
                         self.subscribe_map.insert(key, v);
                     }
                 };
-                if first {
+                if !self.use_grpc && !self.v1_listening {
+                    self.v1_listening = true;
                     ctx.run_later(Duration::from_millis(5), |act, ctx| {
                         act.listener(ctx);
                     });
@@ -262,6 +271,17 @@ impl Handler<ConfigInnerCmd> for ConfigInnerActor {
                 self.grpc_resubscribe(ctx);
                 Ok(ConfigInnerHandleResult::None)
             }
+            ConfigInnerCmd::UpdateProtocol(use_grpc) => {
+                if self.use_grpc != use_grpc {
+                    self.use_grpc = use_grpc;
+                    if use_grpc {
+                        self.grpc_resubscribe(ctx);
+                    } else if !self.v1_listening {
+                        self.listener(ctx);
+                    }
+                }
+                Ok(ConfigInnerHandleResult::None)
+            }
         }
     }
 }
@@ -32,7 +32,10 @@ impl ConfigInnerRequestClient {
             "application/x-www-form-urlencoded".to_owned(),
         );
         let client = reqwest::Client::builder().build().unwrap();
-        let endpoints = ServerEndpointInfo { hosts: vec![host] };
+        let endpoints = ServerEndpointInfo {
+            hosts: vec![host],
+            ..Default::default()
+        };
         Self {
             endpoints: Arc::new(endpoints),
             client,
@@ -42,7 +45,7 @@ impl ConfigInnerRequestClient {
     }
 
     pub fn new_with_endpoint(endpoints: Arc<ServerEndpointInfo>) -> Self {
-        let client = reqwest::Client::builder().build().unwrap();
+        let client = endpoints.http_client();
         let mut headers = HashMap::new();
         headers.insert(
             "Content-Type".to_owned(),
@@ -88,9 +91,8 @@ impl ConfigInnerRequestClient {
         let host = self.endpoints.select_host();
         let token_param = self.get_token().await;
         let url = format!(
-            "http://{}:{}/nacos/v1/cs/configs?{}&{}",
-            host.ip,
-            host.port,
+            "{}/nacos/v1/cs/configs?{}&{}",
+            self.endpoints.get_server_url(host),
             token_param,
             serde_urlencoded::to_string(&param).unwrap()
         );
@@ -100,7 +102,7 @@ impl ConfigInnerRequestClient {
             &url,
             vec![],
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         if !resp.status_is_200() {
@@ -122,8 +124,9 @@ impl ConfigInnerRequestClient {
         let token_param = self.get_token().await;
         let host = self.endpoints.select_host();
         let url = format!(
-            "http://{}:{}/nacos/v1/cs/configs?{}",
-            host.ip, host.port, token_param
+            "{}/nacos/v1/cs/configs?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
 
         let body = serde_urlencoded::to_string(&param).unwrap();
@@ -133,7 +136,7 @@ impl ConfigInnerRequestClient {
             &url,
             body.as_bytes().to_vec(),
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         if !resp.status_is_200() {
@@ -153,8 +156,9 @@ impl ConfigInnerRequestClient {
         let token_param = self.get_token().await;
         let host = self.endpoints.select_host();
         let url = format!(
-            "http://{}:{}/nacos/v1/cs/configs?{}",
-            host.ip, host.port, token_param
+            "{}/nacos/v1/cs/configs?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
         let body = serde_urlencoded::to_string(&param).unwrap();
         let resp = Utils::request(
@@ -163,7 +167,7 @@ impl ConfigInnerRequestClient {
             &url,
             body.as_bytes().to_vec(),
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         if !resp.status_is_200() {
@@ -179,14 +183,16 @@ impl ConfigInnerRequestClient {
         timeout: Option<u64>,
     ) -> anyhow::Result<Vec<ConfigKey>> {
         let mut param: HashMap<&str, &str> = HashMap::new();
-        let timeout = timeout.unwrap_or(30000u64);
+        let timeout =
+            timeout.unwrap_or(self.endpoints.options.long_poll_timeout.as_millis() as u64);
         let timeout_str = timeout.to_string();
         param.insert("Listening-Configs", content);
         let token_param = self.get_token().await;
         let host = self.endpoints.select_host();
         let url = format!(
-            "http://{}:{}/nacos/v1/cs/configs/listener?{}",
-            host.ip, host.port, token_param
+            "{}/nacos/v1/cs/configs/listener?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
         let body = serde_urlencoded::to_string(&param).unwrap();
         let mut headers = self.headers.clone();
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use actix::Addr;
+use tokio::sync::watch;
+
+use crate::conn_manage::{conn_state::ConnState, manage::ConnManage};
+
+use super::{
+    config_client::inner_client::ConfigInnerRequestClient, naming_client::NamingListenerOptions,
+    AuthInfo, ConfigClient, NamingClient, ServerEndpointInfo,
+};
+
+/// 共享同一个服务端连接(ConnManage)的句柄,按命名空间创建 ConfigClient 与 NamingClient;
+/// 由 `ClientBuilder::build_connection` 创建。
+///
+/// 同一命名空间的客户端未释放时直接复用,各命名空间的监听与注册互不影响。
+pub struct NacosConnection {
+    endpoint: Arc<ServerEndpointInfo>,
+    auth_info: Option<AuthInfo>,
+    client_ip: String,
+    use_grpc: bool,
+    naming_options: NamingListenerOptions,
+    conn_manage_addr: Addr<ConnManage>,
+    conn_state: watch::Receiver<ConnState>,
+    config_clients: Mutex<HashMap<String, Weak<ConfigClient>>>,
+    naming_clients: Mutex<HashMap<String, Weak<NamingClient>>>,
+}
+
+impl NacosConnection {
+    pub(crate) fn new(
+        endpoint: Arc<ServerEndpointInfo>,
+        auth_info: Option<AuthInfo>,
+        client_ip: String,
+        use_grpc: bool,
+        naming_options: NamingListenerOptions,
+        conn_manage_addr: Addr<ConnManage>,
+        conn_state: watch::Receiver<ConnState>,
+    ) -> Self {
+        Self {
+            endpoint,
+            auth_info,
+            client_ip,
+            use_grpc,
+            naming_options,
+            conn_manage_addr,
+            conn_state,
+            config_clients: Default::default(),
+            naming_clients: Default::default(),
+        }
+    }
+
+    /// 获取命名空间(tenant)对应的配置客户端
+    pub fn config_client(&self, tenant: &str) -> Arc<ConfigClient> {
+        let mut clients = self.config_clients.lock().unwrap();
+        if let Some(client) = clients.get(tenant).and_then(|e| e.upgrade()) {
+            return client;
+        }
+        let client = self.create_config_client(tenant);
+        clients.retain(|_, v| v.strong_count() > 0);
+        clients.insert(tenant.to_owned(), Arc::downgrade(&client));
+        client
+    }
+
+    /// 获取命名空间对应的服务发现客户端
+    pub fn naming_client(&self, namespace_id: &str) -> Arc<NamingClient> {
+        let mut clients = self.naming_clients.lock().unwrap();
+        if let Some(client) = clients.get(namespace_id).and_then(|e| e.upgrade()) {
+            return client;
+        }
+        let client = self.create_naming_client(namespace_id);
+        clients.retain(|_, v| v.strong_count() > 0);
+        clients.insert(namespace_id.to_owned(), Arc::downgrade(&client));
+        client
+    }
+
+    pub fn connection_state(&self) -> watch::Receiver<ConnState> {
+        self.conn_state.clone()
+    }
+
+    fn create_config_client(&self, tenant: &str) -> Arc<ConfigClient> {
+        let mut request_client = ConfigInnerRequestClient::new_with_endpoint(self.endpoint.clone());
+        let (config_inner_addr, auth_addr) = ConfigClient::init_register(
+            request_client.clone(),
+            self.auth_info.clone(),
+            Some(self.conn_manage_addr.clone().downgrade()),
+            self.use_grpc,
+        );
+        request_client.set_auth_addr(auth_addr);
+        Arc::new(ConfigClient {
+            tenant: tenant.to_owned(),
+            request_client,
+            config_inner_addr,
+            conn_manage_addr: self.conn_manage_addr.clone(),
+            conn_state: self.conn_state.clone(),
+        })
+    }
+
+    fn create_naming_client(&self, namespace_id: &str) -> Arc<NamingClient> {
+        let addrs = NamingClient::init_register(
+            namespace_id.to_owned(),
+            self.client_ip.clone(),
+            self.endpoint.clone(),
+            Some(self.conn_manage_addr.clone().downgrade()),
+            self.use_grpc,
+            self.naming_options.clone(),
+        );
+        Arc::new(NamingClient {
+            namespace_id: namespace_id.to_owned(),
+            register: addrs.0,
+            listener_addr: addrs.1,
+            current_ip: self.client_ip.clone(),
+            _conn_manage_addr: self.conn_manage_addr.clone(),
+            conn_state: self.conn_state.clone(),
+        })
+    }
+}
@@ -0,0 +1,265 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use super::utils::Utils;
+
+/// 选择注册IP时的协议偏好
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpPreference {
+    #[default]
+    PreferIpv4,
+    PreferIpv6,
+    OnlyIpv4,
+    OnlyIpv6,
+}
+
+/// 多网卡时注册IP的选择规则,参考 spring cloud inetutils
+///
+/// 网卡名支持 `*` 通配,如 `eth*`、`docker*`;网段为 CIDR 格式,如 `192.168.0.0/16`。
+#[derive(Debug, Clone, Default)]
+pub struct InetConfig {
+    pub preferred_interfaces: Vec<String>,
+    pub preferred_networks: Vec<String>,
+    pub ignored_interfaces: Vec<String>,
+    pub ip_preference: IpPreference,
+}
+
+impl InetConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_preferred_interfaces(mut self, preferred_interfaces: Vec<String>) -> Self {
+        self.preferred_interfaces = preferred_interfaces;
+        self
+    }
+
+    pub fn set_preferred_networks(mut self, preferred_networks: Vec<String>) -> Self {
+        self.preferred_networks = preferred_networks;
+        self
+    }
+
+    pub fn set_ignored_interfaces(mut self, ignored_interfaces: Vec<String>) -> Self {
+        self.ignored_interfaces = ignored_interfaces;
+        self
+    }
+
+    pub fn set_ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// 按规则从本机网卡中选择IP,没有可用网卡地址时使用默认路由的地址
+    pub fn select_ip(&self) -> String {
+        let default_ip = local_ipaddress::get().and_then(|e| e.parse::<IpAddr>().ok());
+        match self.select_from(&Self::list_interface_addrs(), default_ip) {
+            Some(ip) => ip.to_string(),
+            None => match default_ip {
+                Some(ip) => ip.to_string(),
+                None => "127.0.0.1".to_owned(),
+            },
+        }
+    }
+
+    fn select_from(
+        &self,
+        addrs: &[(String, IpAddr)],
+        default_ip: Option<IpAddr>,
+    ) -> Option<IpAddr> {
+        let networks = self.parse_networks();
+        let mut candidates: Vec<&(String, IpAddr)> = addrs
+            .iter()
+            .filter(|(name, ip)| {
+                self.accept_family(ip)
+                    && !Self::is_link_local(ip)
+                    && !ip.is_loopback()
+                    && !Self::match_any(&self.ignored_interfaces, name)
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        //按协议偏好稳定排序
+        let prefer_v6 = matches!(
+            self.ip_preference,
+            IpPreference::PreferIpv6 | IpPreference::OnlyIpv6
+        );
+        candidates.sort_by_key(|(_, ip)| ip.is_ipv6() != prefer_v6);
+        for pattern in &self.preferred_interfaces {
+            if let Some((_, ip)) = candidates
+                .iter()
+                .find(|(name, _)| Utils::wildcard_match(pattern, name))
+            {
+                return Some(*ip);
+            }
+        }
+        for network in &networks {
+            if let Some((_, ip)) = candidates.iter().find(|(_, ip)| network.contains(ip)) {
+                return Some(*ip);
+            }
+        }
+        if !self.preferred_interfaces.is_empty() || !networks.is_empty() {
+            log::warn!("no local ip matches the preferred interfaces or networks");
+        }
+        //没有匹配的偏好规则时,默认路由的地址属于偏好的协议时优先使用
+        if let Some(default_ip) = default_ip.filter(|ip| ip.is_ipv6() == prefer_v6) {
+            if candidates.iter().any(|(_, ip)| *ip == default_ip) {
+                return Some(default_ip);
+            }
+        }
+        candidates.first().map(|(_, ip)| *ip)
+    }
+
+    fn parse_networks(&self) -> Vec<IpNet> {
+        let mut networks = vec![];
+        for item in &self.preferred_networks {
+            let network = match item.parse::<IpNet>() {
+                Ok(v) => Some(v),
+                Err(_) => item.parse::<IpAddr>().ok().map(IpNet::from),
+            };
+            match network {
+                Some(v) => networks.push(v),
+                None => log::warn!("ignore invalid preferred network:{}", item),
+            }
+        }
+        networks
+    }
+
+    fn accept_family(&self, ip: &IpAddr) -> bool {
+        match self.ip_preference {
+            IpPreference::OnlyIpv4 => ip.is_ipv4(),
+            IpPreference::OnlyIpv6 => ip.is_ipv6(),
+            _ => true,
+        }
+    }
+
+    fn is_link_local(ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(v4) => v4.is_link_local(),
+            IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+        }
+    }
+
+    fn match_any(patterns: &[String], name: &str) -> bool {
+        patterns.iter().any(|e| Utils::wildcard_match(e, name))
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    ))]
+    fn list_interface_addrs() -> Vec<(String, IpAddr)> {
+        use nix::net::if_::InterfaceFlags;
+        let mut list = vec![];
+        let addrs = match nix::ifaddrs::getifaddrs() {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("get local interface address error,{}", e);
+                return list;
+            }
+        };
+        for item in addrs {
+            if !item.flags.contains(InterfaceFlags::IFF_UP) {
+                continue;
+            }
+            let address = match &item.address {
+                Some(v) => v,
+                None => continue,
+            };
+            let ip = if let Some(v) = address.as_sockaddr_in() {
+                IpAddr::V4(v.ip())
+            } else if let Some(v) = address.as_sockaddr_in6() {
+                IpAddr::V6(v.ip())
+            } else {
+                continue;
+            };
+            list.push((item.interface_name, ip));
+        }
+        list
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    )))]
+    fn list_interface_addrs() -> Vec<(String, IpAddr)> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{InetConfig, IpPreference};
+
+    fn build_addrs() -> Vec<(String, IpAddr)> {
+        [
+            ("lo", "127.0.0.1"),
+            ("docker0", "172.17.0.1"),
+            ("eth0", "10.0.1.5"),
+            ("eth0", "fe80::1"),
+            ("eth0", "2001:db8::5"),
+            ("tun0", "192.168.100.2"),
+        ]
+        .iter()
+        .map(|(name, ip)| ((*name).to_owned(), ip.parse().unwrap()))
+        .collect()
+    }
+
+    #[test]
+    fn test_select_ip() {
+        let addrs = build_addrs();
+        let default_ip = Some("192.168.100.2".parse().unwrap());
+        let config = InetConfig::new();
+        assert_eq!(config.select_from(&addrs, default_ip), default_ip);
+
+        let config = InetConfig::new().set_preferred_interfaces(vec!["eth*".to_owned()]);
+        assert_eq!(
+            config.select_from(&addrs, default_ip),
+            Some("10.0.1.5".parse().unwrap())
+        );
+
+        let config = InetConfig::new().set_preferred_networks(vec!["172.16.0.0/12".to_owned()]);
+        assert_eq!(
+            config.select_from(&addrs, default_ip),
+            Some("172.17.0.1".parse().unwrap())
+        );
+
+        //双栈时默认路由的 ipv4 地址不影响 ipv6 偏好
+        let config = InetConfig::new().set_ip_preference(IpPreference::PreferIpv6);
+        assert_eq!(
+            config.select_from(&addrs, default_ip),
+            Some("2001:db8::5".parse().unwrap())
+        );
+        let default_ip_v6 = Some("2001:db8::5".parse().unwrap());
+        assert_eq!(
+            InetConfig::new().select_from(&addrs, default_ip_v6),
+            Some("172.17.0.1".parse().unwrap())
+        );
+
+        let config = InetConfig::new()
+            .set_ignored_interfaces(vec!["docker*".to_owned(), "tun*".to_owned()])
+            .set_ip_preference(IpPreference::PreferIpv6);
+        assert_eq!(
+            config.select_from(&addrs, default_ip),
+            Some("2001:db8::5".parse().unwrap())
+        );
+
+        let config = InetConfig::new()
+            .set_ignored_interfaces(vec!["eth*".to_owned(), "docker*".to_owned()])
+            .set_ip_preference(IpPreference::OnlyIpv6);
+        assert_eq!(config.select_from(&addrs, default_ip), None);
+    }
+}
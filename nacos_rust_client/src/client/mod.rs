@@ -3,8 +3,11 @@ use std::sync::Arc;
 
 pub mod builder;
 pub mod config_client;
+pub mod connection;
+pub mod inet_utils;
 pub mod nacos_client;
 pub mod naming_client;
+pub mod tls;
 
 pub mod utils;
 
@@ -13,12 +16,20 @@ pub mod auth;
 use crypto::digest::Digest;
 use serde::{Deserialize, Serialize};
 
+use crate::conn_manage::ConnectionOptions;
+
 pub use self::builder::ClientBuilder;
 pub use self::config_client::ConfigClient;
+pub use self::connection::NacosConnection;
+pub use self::inet_utils::{InetConfig, IpPreference};
 pub use self::nacos_client::NacosClient;
 pub use self::naming_client::NamingClient;
+pub(crate) use self::tls::TlsClient;
+pub use self::tls::TlsConfig;
+pub use crate::conn_manage::ConnState;
+pub use crate::conn_manage::ProtocolMode;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct HostInfo {
     pub ip: String,
     pub port: u32,
@@ -56,12 +67,26 @@ impl HostInfo {
         }
     }
 
+    /// 解析 `ip:port#grpc_port` 格式的地址,ipv6 地址需要使用中括号,如 `[::1]:8848#9848`
     pub fn parse(addr: &str) -> Self {
-        let strs = addr.split(':').collect::<Vec<_>>();
+        let addr = addr.trim();
+        let (ip, port_str) = if let Some(rest) = addr.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((ip, rest)) => (ip, rest.strip_prefix(':')),
+                None => (rest, None),
+            }
+        } else if addr.matches(':').count() > 1 {
+            //不带中括号的ipv6地址,使用默认端口
+            (addr, None)
+        } else {
+            match addr.split_once(':') {
+                Some((ip, port_str)) => (ip, Some(port_str)),
+                None => (addr, None),
+            }
+        };
         let mut port = 8848u32;
         let mut grpc_port = port + 1000;
-        let ip = strs.get(0).unwrap_or(&"127.0.0.1");
-        if let Some(p) = strs.get(1) {
+        if let Some(p) = port_str {
             let ports = p.split('#').collect::<Vec<_>>();
             if let Some(p) = ports.get(0) {
                 let pstr = (*p).to_owned();
@@ -74,11 +99,20 @@ impl HostInfo {
             }
         }
         Self {
-            ip: (*ip).to_owned(),
+            ip: ip.to_owned(),
             port,
             grpc_port,
         }
     }
+
+    /// http 接口地址,如 `127.0.0.1:8848`、`[::1]:8848`
+    pub fn get_addr(&self) -> String {
+        utils::Utils::format_host_port(&self.ip, self.port)
+    }
+
+    pub fn get_grpc_addr(&self) -> String {
+        utils::Utils::format_host_port(&self.ip, self.grpc_port)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -141,9 +175,17 @@ pub struct TokenInfo {
     pub token_ttl: u64,
 }
 
+/// 服务端地址及请求配置
+///
+/// 通过 `new`、`from_hosts` 或 `Default` 创建,TLS 与连接参数通过 setter 设置;
+/// 标记为 non_exhaustive,以便后续增加字段时不影响调用方。
 #[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct ServerEndpointInfo {
     pub hosts: Vec<HostInfo>,
+    /// 设置后使用 https 请求服务端
+    pub(crate) tls: Option<TlsClient>,
+    pub(crate) options: Arc<ConnectionOptions>,
 }
 
 impl ServerEndpointInfo {
@@ -156,7 +198,46 @@ impl ServerEndpointInfo {
         if hosts.len() == 0 {
             hosts.push(HostInfo::parse("127.0.0.1:8848"));
         }
-        Self { hosts: hosts }
+        Self {
+            hosts,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_hosts(hosts: Vec<HostInfo>) -> Self {
+        Self {
+            hosts,
+            ..Default::default()
+        }
+    }
+
+    /// 使用 TLS 时 http 请求改为 https;证书在设置时加载,加载失败时返回错误
+    pub fn set_tls_config(mut self, tls: Option<TlsConfig>) -> anyhow::Result<Self> {
+        self.tls = match &tls {
+            Some(tls) => Some(TlsClient::new(tls)?),
+            None => None,
+        };
+        Ok(self)
+    }
+
+    /// 请求超时与重试等连接参数
+    pub fn set_connection_options(mut self, options: ConnectionOptions) -> Self {
+        self.options = Arc::new(options);
+        self
+    }
+
+    /// http 请求的服务端地址,如 `http://127.0.0.1:8848`
+    pub fn get_server_url(&self, host: &HostInfo) -> String {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        format!("{}://{}", scheme, host.get_addr())
+    }
+
+    /// 使用 TLS 时共享创建连接时加载的 http 客户端
+    pub(crate) fn http_client(&self) -> reqwest::Client {
+        match &self.tls {
+            Some(tls) => tls.http_client(),
+            None => reqwest::Client::new(),
+        }
     }
 
     pub fn select_host(&self) -> &HostInfo {
@@ -193,7 +274,7 @@ impl Client {
         );
 
         let host = endpoints.select_host();
-        let url = format!("http://{}:{}/nacos/v1/auth/login", host.ip, host.port);
+        let url = format!("{}/nacos/v1/auth/login", endpoints.get_server_url(host));
         let resp = utils::Utils::request(
             client,
             "POST",
@@ -203,7 +284,7 @@ impl Client {
                 .as_bytes()
                 .to_vec(),
             Some(&headers),
-            Some(3000),
+            Some(endpoints.options.request_timeout_millis()),
         )
         .await?;
         if !resp.status_is_200() {
@@ -215,7 +296,30 @@ impl Client {
     }
 }
 
-pub enum ProtocolMode {
-    Http,
-    Grpc,
+#[cfg(test)]
+mod tests {
+    use super::HostInfo;
+
+    #[test]
+    fn test_parse_host_info() {
+        let host = HostInfo::parse("127.0.0.1:8848#9849");
+        assert_eq!(host.ip, "127.0.0.1");
+        assert_eq!(host.port, 8848);
+        assert_eq!(host.grpc_port, 9849);
+        let host = HostInfo::parse("[::1]:8848#9848");
+        assert_eq!(host.ip, "::1");
+        assert_eq!(host.port, 8848);
+        assert_eq!(host.grpc_port, 9848);
+        assert_eq!(host.get_addr(), "[::1]:8848");
+        assert_eq!(host.get_grpc_addr(), "[::1]:9848");
+        let host = HostInfo::parse("[fe80::1]");
+        assert_eq!(host.ip, "fe80::1");
+        assert_eq!(host.port, 8848);
+        let host = HostInfo::parse("fe80::1");
+        assert_eq!(host.ip, "fe80::1");
+        assert_eq!(host.grpc_port, 9848);
+        let host = HostInfo::parse("nacos.local:8849");
+        assert_eq!(host.get_addr(), "nacos.local:8849");
+        assert_eq!(host.grpc_port, 9849);
+    }
 }
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use std::sync::Arc;
+
+use crate::conn_manage::conn_msg::ServiceResult;
+
 use super::Instance;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -25,9 +29,30 @@ pub struct BeatRequest {
     pub cluster_name: String,
     pub group_name: String,
     pub ephemeral: Option<String>,
+    pub ip: Option<String>,
+    pub port: Option<u32>,
+    /// 轻量心跳时为空
     pub beat: String,
 }
 
+/// nacos 1.x udp 推送的数据包
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PushPacket {
+    pub r#type: Option<String>,
+    pub last_ref_time: Option<i64>,
+    pub data: Option<String>,
+}
+
+/// v1 心跳接口的返回值
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BeatResponse {
+    pub client_beat_interval: Option<u64>,
+    pub light_beat_enabled: Option<bool>,
+    pub code: Option<i32>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct InstanceWebParams {
@@ -119,6 +144,24 @@ impl InstanceVO {
         }
         instance
     }
+
+    pub fn from_instance(instance: &Instance) -> Self {
+        let service = instance.get_service_named();
+        Self {
+            service: Some(service.clone()),
+            ip: Some(instance.ip.to_owned()),
+            port: Some(instance.port),
+            cluster_name: Some(instance.cluster_name.to_owned()),
+            weight: Some(instance.weight),
+            healthy: Some(instance.healthy),
+            instance_id: None,
+            metadata: instance.metadata.clone(),
+            marked: None,
+            enabled: Some(instance.enabled),
+            service_name: Some(service),
+            ephemeral: Some(instance.ephemeral),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -139,6 +182,26 @@ pub struct QueryListResult {
     pub metadata: Option<HashMap<String, String>>,
 }
 
+impl QueryListResult {
+    pub fn to_service_result(self) -> ServiceResult {
+        let hosts = self
+            .hosts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| Arc::new(e.to_instance()))
+            .collect();
+        ServiceResult {
+            hosts,
+            cache_millis: self.cache_millis,
+            protect_threshold: self.protect_threshold,
+            metadata: self.metadata,
+            clusters: self.clusters,
+            checksum: self.checksum,
+            last_ref_time: self.last_ref_time,
+        }
+    }
+}
+
 pub struct NamingUtils;
 
 impl NamingUtils {
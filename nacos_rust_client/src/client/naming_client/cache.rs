@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use crate::conn_manage::conn_msg::ServiceResult;
+
+use super::{InstanceVO, QueryListResult};
+
+/// 与 java 客户端一致的故障转移开关文件名,内容为 `1` 时开启
+const FAILOVER_SWITCH_FILE: &str = "00-00---000-VIPSRV_FAILOVER_SWITCH-000---00-00";
+const FAILOVER_DIR: &str = "failover";
+
+/// 服务实例本地磁盘缓存配置
+///
+/// 缓存文件位于 `{cache_dir}/{namespace_id}/{group@@service}`(文件名按 java 客户端的 URLEncoder 规则转义),格式与 nacos 查询实例列表的返回值一致;
+/// 在 `{cache_dir}/{namespace_id}/failover/` 下放置开关文件后进入故障转移模式,
+/// 此时只使用 failover 目录(不存在时使用缓存目录)中的实例列表,忽略服务端推送。
+#[derive(Debug, Clone)]
+pub struct NamingCacheConfig {
+    pub cache_dir: PathBuf,
+    /// 首次订阅服务时是否先加载磁盘缓存
+    pub load_at_start: bool,
+}
+
+impl NamingCacheConfig {
+    pub fn new(cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            cache_dir: cache_dir.into(),
+            load_at_start: true,
+        }
+    }
+
+    pub fn set_load_at_start(mut self, load_at_start: bool) -> Self {
+        self.load_at_start = load_at_start;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct NamingDiskCache {
+    dir: PathBuf,
+    pub load_at_start: bool,
+}
+
+impl NamingDiskCache {
+    pub fn new(config: &NamingCacheConfig, namespace_id: &str) -> Self {
+        let namespace_id = if namespace_id.is_empty() {
+            "public"
+        } else {
+            namespace_id
+        };
+        Self {
+            dir: config.cache_dir.join(namespace_id),
+            load_at_start: config.load_at_start,
+        }
+    }
+
+    fn failover_dir(&self) -> PathBuf {
+        self.dir.join(FAILOVER_DIR)
+    }
+
+    pub fn is_failover_switch_on(&self) -> bool {
+        match std::fs::read_to_string(self.failover_dir().join(FAILOVER_SWITCH_FILE)) {
+            Ok(v) => v.trim() == "1",
+            Err(_) => false,
+        }
+    }
+
+    pub fn write(&self, key: &str, result: &ServiceResult) -> anyhow::Result<()> {
+        let value = QueryListResult {
+            name: Some(key.to_owned()),
+            cache_millis: result.cache_millis,
+            hosts: Some(
+                result
+                    .hosts
+                    .iter()
+                    .map(|e| InstanceVO::from_instance(e))
+                    .collect(),
+            ),
+            protect_threshold: result.protect_threshold,
+            metadata: result.metadata.clone(),
+            clusters: result.clusters.clone(),
+            checksum: result.checksum.clone(),
+            last_ref_time: result.last_ref_time,
+            ..Default::default()
+        };
+        let content = serde_json::to_vec(&value)?;
+        std::fs::create_dir_all(&self.dir)?;
+        //先写临时文件再改名,避免进程中断时留下不完整的缓存
+        let file_name = encode_file_name(key);
+        let tmp_file = self.dir.join(format!(".{}.tmp", &file_name));
+        std::fs::write(&tmp_file, content)?;
+        std::fs::rename(&tmp_file, self.dir.join(file_name))?;
+        Ok(())
+    }
+
+    pub fn read(&self, key: &str) -> anyhow::Result<QueryListResult> {
+        Self::read_file(&self.dir.join(encode_file_name(key)))
+    }
+
+    /// 故障转移数据,failover 目录中不存在时使用缓存数据
+    pub fn read_failover(&self, key: &str) -> anyhow::Result<QueryListResult> {
+        let path = self.failover_dir().join(encode_file_name(key));
+        if path.exists() {
+            Self::read_file(&path)
+        } else {
+            self.read(key)
+        }
+    }
+
+    fn read_file(path: &Path) -> anyhow::Result<QueryListResult> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+/// 与 java 客户端 `URLEncoder.encode(key, "UTF-8")` 一致,避免服务名中的 `/` 等字符影响缓存路径
+fn encode_file_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for b in key.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'*' => {
+                name.push(b as char)
+            }
+            b' ' => name.push('+'),
+            _ => name.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        encode_file_name, NamingCacheConfig, NamingDiskCache, FAILOVER_DIR, FAILOVER_SWITCH_FILE,
+    };
+    use crate::client::naming_client::Instance;
+    use crate::conn_manage::conn_msg::ServiceResult;
+
+    #[test]
+    fn test_write_and_read_cache() {
+        let dir = std::env::temp_dir().join(format!("nacos_naming_cache_{}", std::process::id()));
+        let cache = NamingDiskCache::new(&NamingCacheConfig::new(&dir), "");
+        let key = "DEFAULT_GROUP@@foo";
+        let mut instance = Instance::new_simple("127.0.0.1", 8080, "foo", "DEFAULT_GROUP");
+        instance.cluster_name = "c1".to_owned();
+        let result = ServiceResult {
+            hosts: vec![Arc::new(instance)],
+            cache_millis: Some(1000),
+            metadata: Some(
+                [("route".to_owned(), "v2".to_owned())]
+                    .into_iter()
+                    .collect(),
+            ),
+            checksum: Some("abc".to_owned()),
+            ..Default::default()
+        };
+        cache.write(key, &result).unwrap();
+        let value = cache.read_failover(key).unwrap().to_service_result();
+        let detail = value.get_detail();
+        assert_eq!(detail.metadata.get("route").unwrap(), "v2");
+        assert_eq!(detail.checksum.as_deref(), Some("abc"));
+        let hosts = value.hosts;
+        assert_eq!(hosts.len(), 1);
+        let instance = hosts.into_iter().next().unwrap();
+        assert_eq!(instance.ip, "127.0.0.1");
+        assert_eq!(instance.port, 8080);
+        assert_eq!(instance.cluster_name, "c1");
+        assert_eq!(instance.service_name, "foo");
+
+        assert!(!cache.is_failover_switch_on());
+        let failover_dir = dir.join("public").join(FAILOVER_DIR);
+        std::fs::create_dir_all(&failover_dir).unwrap();
+        std::fs::write(failover_dir.join(FAILOVER_SWITCH_FILE), "1\n").unwrap();
+        assert!(cache.is_failover_switch_on());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_encode_file_name() {
+        assert_eq!(
+            encode_file_name("DEFAULT_GROUP@@foo.bar"),
+            "DEFAULT_GROUP%40%40foo.bar"
+        );
+        assert_eq!(
+            encode_file_name("g@@../../etc/passwd@@c 1"),
+            "g%40%40..%2F..%2Fetc%2Fpasswd%40%40c+1"
+        );
+    }
+}
@@ -1,34 +1,45 @@
-use crate::client::auth::AuthActor;
 use crate::client::nacos_client::ActixSystemActorSetCmd;
 use crate::client::nacos_client::ActixSystemCmd;
 use crate::client::nacos_client::ActixSystemResult;
 use crate::client::AuthInfo;
 use crate::client::ClientInfo;
 use crate::client::ServerEndpointInfo;
-use crate::conn_manage::manage::ConnManage;
+use crate::conn_manage::conn_state::ConnState;
+use crate::conn_manage::manage::{ConnManage, ConnManageCmd};
 use crate::init_global_system_actor;
 use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use super::watch::WatchListener;
+use super::DeregisterResult;
 use super::Instance;
 use super::InstanceListener;
+use super::LabelSelector;
+use super::NamingListenerOptions;
 use super::NamingQueryCmd;
 use super::NamingQueryResult;
 use super::QueryInstanceListParams;
+use super::ServiceDetail;
 use super::ServiceInstanceKey;
+use super::ServiceWatchStream;
 use super::{
-    InnerNamingListener, InnerNamingRegister, InnerNamingRequestClient, NamingListenerCmd,
-    NamingRegisterCmd, UdpWorker,
+    InnerNamingListener, InnerNamingRegister, NamingListenerCmd, NamingRegisterCmd, UdpWorker,
 };
 use crate::client::{utils, HostInfo};
 use actix::prelude::*;
 use actix::WeakAddr;
 
+//subscribe 使用的监听id为0,watch_service 使用独立的id,以便单独取消
+static WATCH_LISTENER_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct NamingClient {
     pub namespace_id: String,
     pub(crate) register: Addr<InnerNamingRegister>,
     pub(crate) listener_addr: Addr<InnerNamingListener>,
     pub(crate) _conn_manage_addr: Addr<ConnManage>,
+    pub(crate) conn_state: tokio::sync::watch::Receiver<ConnState>,
     pub current_ip: String,
 }
 
@@ -46,7 +57,7 @@ impl NamingClient {
             Ok(v) => v,
             Err(_) => local_ipaddress::get().unwrap_or("127.0.0.1".to_owned()),
         };
-        let endpoint = Arc::new(ServerEndpointInfo { hosts: vec![host] });
+        let endpoint = Arc::new(ServerEndpointInfo::from_hosts(vec![host]));
         let conn_manage = ConnManage::new(
             endpoint.hosts.clone(),
             use_grpc,
@@ -54,15 +65,15 @@ impl NamingClient {
             Default::default(),
             Default::default(),
         );
+        let conn_state = conn_manage.subscribe_state();
         let conn_manage_addr = conn_manage.start_at_global_system();
-        let request_client = InnerNamingRequestClient::new_with_endpoint(endpoint);
         let addrs = Self::init_register(
             namespace_id.clone(),
             current_ip.clone(),
-            request_client,
-            None,
+            endpoint,
             Some(conn_manage_addr.clone().downgrade()),
             use_grpc,
+            Default::default(),
         );
         let r = Arc::new(Self {
             namespace_id,
@@ -70,6 +81,7 @@ impl NamingClient {
             listener_addr: addrs.1,
             current_ip,
             _conn_manage_addr: conn_manage_addr,
+            conn_state,
         });
         let system_addr = init_global_system_actor();
         system_addr.do_send(ActixSystemActorSetCmd::LastNamingClient(r.clone()));
@@ -90,8 +102,8 @@ impl NamingClient {
             Default::default(),
             Default::default(),
         );
+        let conn_state = conn_manage.subscribe_state();
         let conn_manage_addr = conn_manage.start_at_global_system();
-        let request_client = InnerNamingRequestClient::new_with_endpoint(endpoint);
         let current_ip = match env::var("NACOS_CLIENT_IP") {
             Ok(v) => v,
             Err(_) => local_ipaddress::get().unwrap_or("127.0.0.1".to_owned()),
@@ -99,10 +111,10 @@ impl NamingClient {
         let addrs = Self::init_register(
             namespace_id.clone(),
             current_ip.clone(),
-            request_client,
-            auth_info,
+            endpoint,
             Some(conn_manage_addr.clone().downgrade()),
             use_grpc,
+            Default::default(),
         );
         let r = Arc::new(Self {
             namespace_id,
@@ -110,6 +122,7 @@ impl NamingClient {
             listener_addr: addrs.1,
             current_ip,
             _conn_manage_addr: conn_manage_addr,
+            conn_state,
         });
         let system_addr = init_global_system_actor();
         system_addr.do_send(ActixSystemActorSetCmd::LastNamingClient(r.clone()));
@@ -119,24 +132,15 @@ impl NamingClient {
     pub(crate) fn init_register(
         namespace_id: String,
         client_ip: String,
-        mut request_client: InnerNamingRequestClient,
-        auth_info: Option<AuthInfo>,
+        endpoint: Arc<ServerEndpointInfo>,
         conn_manage_addr: Option<WeakAddr<ConnManage>>,
         use_grpc: bool,
+        options: NamingListenerOptions,
     ) -> (Addr<InnerNamingRegister>, Addr<InnerNamingListener>) {
         let system_addr = init_global_system_actor();
-        let endpoint = request_client.endpoints.clone();
-        let actor = AuthActor::new(endpoint, auth_info);
-        let (tx, rx) = std::sync::mpsc::sync_channel(1);
-        let msg = ActixSystemCmd::AuthActor(actor, tx);
-        system_addr.do_send(msg);
-        let auth_addr = match rx.recv().unwrap() {
-            ActixSystemResult::AuthActorAddr(auth_addr) => auth_addr,
-            _ => panic!("init actor error"),
-        };
-        request_client.set_auth_addr(auth_addr);
 
-        let actor = InnerNamingRegister::new(use_grpc, conn_manage_addr.clone());
+        let actor = InnerNamingRegister::new(use_grpc, conn_manage_addr.clone())
+            .set_reconcile_config(options.reconcile_config);
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let msg = ActixSystemCmd::InnerNamingRegister(actor, tx);
         system_addr.do_send(msg);
@@ -145,7 +149,7 @@ impl NamingClient {
             _ => panic!("init actor error"),
         };
 
-        let actor = UdpWorker::new(None);
+        let actor = UdpWorker::new(None).set_config(options.udp_config, endpoint.hosts.clone());
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let msg = ActixSystemCmd::UdpWorker(actor, tx);
         system_addr.do_send(msg);
@@ -158,11 +162,12 @@ impl NamingClient {
             &namespace_id,
             &client_ip,
             0,
-            request_client,
             udp_work_addr,
             conn_manage_addr,
             use_grpc,
-        );
+        )
+        .set_protect_config(options.protect_config)
+        .set_cache_config(options.cache_config);
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let msg = ActixSystemCmd::InnerNamingListener(actor, tx);
         system_addr.do_send(msg);
@@ -179,16 +184,48 @@ impl NamingClient {
         self.listener_addr.do_send(NamingListenerCmd::Close);
     }
 
+    /// 注册实例;实例ip为空时使用客户端IP
     pub fn register(&self, mut instance: Instance) {
         instance.namespace_id = self.namespace_id.clone();
+        if instance.ip.is_empty() {
+            instance.ip = self.current_ip.clone();
+        }
         self.register.do_send(NamingRegisterCmd::Register(instance));
     }
 
     pub fn unregister(&self, mut instance: Instance) {
         instance.namespace_id = self.namespace_id.clone();
+        if instance.ip.is_empty() {
+            instance.ip = self.current_ip.clone();
+        }
         self.register.do_send(NamingRegisterCmd::Remove(instance));
     }
 
+    /// 优雅下线:先把本客户端注册的实例权重设为0,等待 grace 让订阅方感知后再注销,
+    /// 返回每个实例的注销结果;调用后客户端不再提供注册与订阅功能。
+    pub async fn drain_and_shutdown(
+        &self,
+        grace: Duration,
+    ) -> anyhow::Result<Vec<DeregisterResult>> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.register
+            .do_send(NamingRegisterCmd::DrainAndShutdown(grace, tx));
+        let results = rx.await?;
+        self.listener_addr.do_send(NamingListenerCmd::Close);
+        Ok(results)
+    }
+
+    /// 运行时更新服务端地址列表,当前连接的地址被移除时会切换连接并重新订阅
+    pub fn update_server_hosts(&self, hosts: Vec<HostInfo>) {
+        self._conn_manage_addr
+            .do_send(ConnManageCmd::UpdateHosts(hosts));
+    }
+
+    /// 订阅与服务端的连接状态,可用于就绪检查与告警
+    pub fn connection_state(&self) -> tokio::sync::watch::Receiver<ConnState> {
+        self.conn_state.clone()
+    }
+
     pub async fn query_instances(
         &self,
         mut params: QueryInstanceListParams,
@@ -203,6 +240,21 @@ impl NamingClient {
         }
     }
 
+    /// 查询服务信息(元数据、checksum等)与满足条件的实例
+    pub async fn query_service(
+        &self,
+        mut params: QueryInstanceListParams,
+    ) -> anyhow::Result<(Arc<ServiceDetail>, Vec<Arc<Instance>>)> {
+        params.namespace_id = self.namespace_id.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.listener_addr
+            .do_send(NamingQueryCmd::QueryService(params, tx));
+        match rx.await? {
+            NamingQueryResult::Service(service, list) => Ok((service, list)),
+            _ => Err(anyhow::anyhow!("not found service")),
+        }
+    }
+
     pub async fn select_instance(
         &self,
         mut params: QueryInstanceListParams,
@@ -233,6 +285,48 @@ impl NamingClient {
         //let msg=NamingListenerCmd::AddHeartbeat(key.clone());
         //self.listener_addr.do_send(msg);
         let id = 0u64;
+        self.init_service_instances(&key).await;
+        let msg = NamingListenerCmd::Add(key, id, listener);
+        self.listener_addr.do_send(msg);
+        Ok(())
+    }
+
+    /// 订阅服务,只通知元数据满足selector的实例变更
+    pub async fn subscribe_with_selector<T: InstanceListener + Send + 'static>(
+        &self,
+        key: ServiceInstanceKey,
+        selector: LabelSelector,
+        listener: Box<T>,
+    ) -> anyhow::Result<()> {
+        let id = 0u64;
+        self.init_service_instances(&key).await;
+        let msg = NamingListenerCmd::AddWithSelector(key, id, selector, listener);
+        self.listener_addr.do_send(msg);
+        Ok(())
+    }
+
+    /// 以Stream的方式监听服务实例变更;第一个元素为当前实例快照,之后为增量变更。
+    ///
+    /// params 用于过滤事件中的实例;Stream drop 时自动取消监听。
+    pub async fn watch_service(
+        &self,
+        key: ServiceInstanceKey,
+        params: QueryInstanceListParams,
+    ) -> ServiceWatchStream {
+        let id = WATCH_LISTENER_ID.fetch_add(1, Ordering::Relaxed);
+        self.init_service_instances(&key).await;
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let listener = WatchListener::new(key.clone(), params, tx);
+        let msg = NamingListenerCmd::Watch(key.clone(), id, Box::new(listener));
+        self.listener_addr.do_send(msg);
+        ServiceWatchStream::new(key, id, rx, self.listener_addr.clone())
+    }
+
+    async fn init_service_instances(&self, key: &ServiceInstanceKey) {
+        //指定集群的订阅由监听器单独订阅,不使用全量实例初始化
+        if key.clusters.is_some() {
+            return;
+        }
         //如果之前没有数据，会触发加载数据
         let params = QueryInstanceListParams::new(
             &self.namespace_id,
@@ -247,9 +341,6 @@ impl NamingClient {
             }
             Err(_) => {}
         };
-        let msg = NamingListenerCmd::Add(key, id, listener);
-        self.listener_addr.do_send(msg);
-        Ok(())
     }
 
     pub async fn unsubscribe(&self, key: ServiceInstanceKey) -> anyhow::Result<()> {
@@ -1,12 +1,13 @@
 use crate::client::naming_client::Duration;
-use crate::client::naming_client::InnerNamingRequestClient;
 use crate::client::naming_client::NamingUtils;
+use crate::client::naming_client::PushPacket;
 use crate::client::naming_client::QueryListResult;
 use crate::client::naming_client::ServiceInstanceKey;
 use crate::client::naming_client::UdpDataCmd;
 use crate::client::naming_client::UdpWorker;
 use crate::client::now_millis;
 use crate::client::utils::Utils;
+use crate::client::HostInfo;
 use crate::conn_manage::conn_msg::NamingRequest;
 use crate::conn_manage::conn_msg::NamingResponse;
 use crate::conn_manage::conn_msg::ServiceResult;
@@ -18,19 +19,105 @@ use inner_mem_cache::TimeoutSet;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use super::cache::NamingDiskCache;
 use super::udp_actor::{InitLocalAddr, UdpWorkerCmd};
-use super::{Instance, QueryInstanceListParams};
+use super::{
+    Instance, InstanceProtectConfig, LabelSelector, NamingCacheConfig, QueryInstanceListParams,
+    ServiceDetail,
+};
 
 type InstanceListenerValue = Vec<Arc<Instance>>;
 pub trait InstanceListener {
     fn get_key(&self) -> ServiceInstanceKey;
+    /// 实现了 change_event 的监听器可以不实现
     fn change(
         &self,
-        key: &ServiceInstanceKey,
-        value: &InstanceListenerValue,
-        add_list: &InstanceListenerValue,
-        remove_list: &InstanceListenerValue,
-    ) -> ();
+        _key: &ServiceInstanceKey,
+        _value: &InstanceListenerValue,
+        _add_list: &InstanceListenerValue,
+        _remove_list: &InstanceListenerValue,
+    ) {
+    }
+
+    /// 服务实例变更事件,包含新增、删除及属性变更的实例(含权重为0的实例);
+    /// 默认去掉权重为0的实例后转调 change,权重变为0视为删除
+    fn change_event(&self, event: &ServiceChangeEvent) {
+        let event = event.filter(|e| e.has_weight());
+        if event.is_empty() {
+            return;
+        }
+        self.change(
+            &event.key,
+            &event.instances,
+            &event.add_list,
+            &event.remove_list,
+        );
+    }
+}
+
+/// 实例属性(权重、健康状态、元数据等)变更前后的值
+#[derive(Debug, Clone)]
+pub struct InstanceUpdate {
+    pub old: Arc<Instance>,
+    pub new: Arc<Instance>,
+}
+
+/// 服务实例变更事件
+#[derive(Debug, Clone, Default)]
+pub struct ServiceChangeEvent {
+    pub key: ServiceInstanceKey,
+    /// 变更后的全量实例
+    pub instances: InstanceListenerValue,
+    pub add_list: InstanceListenerValue,
+    pub remove_list: InstanceListenerValue,
+    pub modify_list: Vec<InstanceUpdate>,
+    /// 变更后的服务信息
+    pub service: Arc<ServiceDetail>,
+    /// 服务元数据是否有变化
+    pub metadata_changed: bool,
+}
+
+impl ServiceChangeEvent {
+    pub fn is_empty(&self) -> bool {
+        self.add_list.is_empty()
+            && self.remove_list.is_empty()
+            && self.modify_list.is_empty()
+            && !self.metadata_changed
+    }
+
+    /// 按条件过滤事件;属性变更导致匹配状态变化的实例,转为新增或删除
+    pub fn filter<F>(&self, f: F) -> Self
+    where
+        F: Fn(&Instance) -> bool,
+    {
+        let filter_list = |list: &InstanceListenerValue| -> InstanceListenerValue {
+            list.iter().filter(|e| f(e)).cloned().collect()
+        };
+        let mut add_list = filter_list(&self.add_list);
+        let mut remove_list = filter_list(&self.remove_list);
+        let mut modify_list = vec![];
+        for item in &self.modify_list {
+            match (f(&item.old), f(&item.new)) {
+                (true, true) => modify_list.push(item.clone()),
+                (false, true) => add_list.push(item.new.clone()),
+                (true, false) => remove_list.push(item.old.clone()),
+                (false, false) => {}
+            }
+        }
+        Self {
+            key: self.key.clone(),
+            instances: filter_list(&self.instances),
+            add_list,
+            remove_list,
+            modify_list,
+            service: self.service.clone(),
+            metadata_changed: self.metadata_changed,
+        }
+    }
+
+    pub fn filter_by_selector(&self, selector: &LabelSelector) -> Self {
+        self.filter(|e| selector.matches_instance(e))
+    }
 }
 
 #[derive(Clone)]
@@ -116,17 +203,40 @@ impl InstanceListener for InstanceDefaultListener {
 struct ListenerValue {
     pub listener: Box<dyn InstanceListener + Send>,
     pub id: u64,
+    pub selector: Option<LabelSelector>,
 }
 
 impl ListenerValue {
-    fn new(_: ServiceInstanceKey, listener: Box<dyn InstanceListener + Send>, id: u64) -> Self {
-        Self { listener, id }
+    fn new(
+        _: ServiceInstanceKey,
+        listener: Box<dyn InstanceListener + Send>,
+        id: u64,
+        selector: Option<LabelSelector>,
+    ) -> Self {
+        Self {
+            listener,
+            id,
+            selector,
+        }
+    }
+
+    fn change(&self, event: &ServiceChangeEvent) {
+        if let Some(selector) = &self.selector {
+            let event = event.filter_by_selector(selector);
+            if event.is_empty() {
+                return;
+            }
+            self.listener.change_event(&event);
+        } else {
+            self.listener.change_event(event);
+        }
     }
 }
 
 #[derive(Debug, Default, Clone)]
 struct InstancesWrap {
     instances: Vec<Arc<Instance>>,
+    service: Arc<ServiceDetail>,
     params: QueryInstanceListParams,
     //last_sign:String,
     next_time: u64,
@@ -139,13 +249,16 @@ pub struct InnerNamingListener {
     listeners: HashMap<String, Vec<ListenerValue>>,
     instances: HashMap<String, InstancesWrap>,
     timeout_set: TimeoutSet<String>,
-    request_client: InnerNamingRequestClient,
     conn_manage: Option<WeakAddr<ConnManage>>,
     period: u64,
     client_ip: String,
     udp_port: u16,
     udp_addr: Addr<UdpWorker>,
     use_grpc: bool,
+    protect_config: InstanceProtectConfig,
+    cache: Option<NamingDiskCache>,
+    //故障转移模式下忽略服务端数据
+    failover: bool,
 }
 
 impl InnerNamingListener {
@@ -153,7 +266,6 @@ impl InnerNamingListener {
         namespace_id: &str,
         client_ip: &str,
         udp_port: u16,
-        request_client: InnerNamingRequestClient,
         udp_addr: Addr<UdpWorker>,
         conn_manage: Option<WeakAddr<ConnManage>>,
         use_grpc: bool,
@@ -163,16 +275,28 @@ impl InnerNamingListener {
             listeners: Default::default(),
             instances: Default::default(),
             timeout_set: Default::default(),
-            request_client,
             conn_manage,
             period: 3000,
             client_ip: client_ip.to_owned(),
             udp_port: udp_port,
             udp_addr,
             use_grpc,
+            protect_config: Default::default(),
+            cache: None,
+            failover: false,
         }
     }
 
+    pub fn set_protect_config(mut self, protect_config: InstanceProtectConfig) -> Self {
+        self.protect_config = protect_config;
+        self
+    }
+
+    pub fn set_cache_config(mut self, cache_config: Option<NamingCacheConfig>) -> Self {
+        self.cache = cache_config.map(|e| NamingDiskCache::new(&e, &self.namespace_id));
+        self
+    }
+
     pub fn query_instance(&self, key: String, ctx: &mut actix::Context<Self>) {
         if self.use_grpc {
             return;
@@ -189,15 +313,13 @@ impl InnerNamingListener {
                             .send(NamingRequest::QueryInstance(Box::new(params.clone())))
                             .await
                         {
-                            Ok(res) => match res as anyhow::Result<NamingResponse> {
-                                Ok(res) => match res {
-                                    NamingResponse::ServiceResult(service_result) => {
-                                        return (key, Ok(service_result));
-                                    }
-                                    NamingResponse::None => {}
-                                },
-                                _ => {}
-                            },
+                            Ok(res) => {
+                                if let Ok(NamingResponse::ServiceResult(service_result)) =
+                                    res as anyhow::Result<NamingResponse>
+                                {
+                                    return (key, Ok(service_result));
+                                }
+                            }
                             Err(_err) => {}
                         }
                     }
@@ -213,6 +335,7 @@ impl InnerNamingListener {
                     }
                     Err(e) => {
                         log::error!("get_instance_list error:{}", e);
+                        act.load_cache_instances(&key, false);
                     }
                 };
             })
@@ -228,36 +351,123 @@ impl InnerNamingListener {
         if let Some(cache_millis) = result.cache_millis {
             self.period = cache_millis as u64;
         }
+        if self.failover {
+            if let Some(instance_warp) = self.instances.get_mut(&key) {
+                instance_warp.next_time = now_millis() + self.period;
+            }
+            log::debug!("ignore instances update in failover mode,key:{}", &key);
+            return Ok(());
+        }
+        self.apply_service_result(key, result, false);
+        Ok(())
+    }
+
+    /// 使用磁盘缓存更新实例列表,故障转移模式下使用故障转移数据;返回是否加载成功
+    fn load_cache_instances(&mut self, key: &str, force: bool) -> bool {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return false,
+        };
+        match self.instances.get(key) {
+            Some(instance_warp) if force || instance_warp.instances.is_empty() => {}
+            _ => return false,
+        }
+        let res = if self.failover {
+            cache.read_failover(key)
+        } else {
+            cache.read(key)
+        };
+        match res {
+            Ok(result) => {
+                log::info!("load instances from local cache,key:{}", key);
+                let result = result.to_service_result();
+                self.apply_service_result(key.to_owned(), result, true);
+                true
+            }
+            Err(e) => {
+                log::debug!("load instances cache error,key:{},{}", key, e);
+                false
+            }
+        }
+    }
+
+    fn apply_service_result(&mut self, key: String, result: ServiceResult, from_cache: bool) {
         let mut is_notify = false;
+        let mut metadata_changed = false;
         let mut old_instance_map = HashMap::new();
         if let Some(instance_warp) = self.instances.get_mut(&key) {
+            let current_time = now_millis();
+            instance_warp.next_time = current_time + self.period;
+            //checksum未变化说明服务数据没有变化
+            if let Some(checksum) = &result.checksum {
+                if instance_warp.service.checksum.as_ref() == Some(checksum) {
+                    log::debug!("ignore unchanged instances,key:{}", &key);
+                    return;
+                }
+            }
+            if !from_cache {
+                if let Some(reason) = self.protect_config.check(
+                    &instance_warp.instances,
+                    &result.hosts,
+                    result.protect_threshold,
+                ) {
+                    log::warn!(
+                        "ignore instances update by protection,key:{},{}",
+                        &key,
+                        reason
+                    );
+                    return;
+                }
+            }
             for e in &instance_warp.instances {
-                old_instance_map.insert(format!("{}:{}", e.ip, e.port), e.clone());
+                old_instance_map.insert(e.get_ip_port(), e.clone());
             }
-            instance_warp.instances = result
-                .hosts
-                .into_iter()
-                .filter(|e| e.weight > 0.001f32)
-                .collect();
+            let service = result.get_detail();
+            metadata_changed = service.metadata != instance_warp.service.metadata;
+            instance_warp.service = Arc::new(service);
+            instance_warp.instances = result.hosts.clone();
             is_notify = true;
-            let current_time = now_millis();
-            instance_warp.next_time = current_time + self.period;
         }
         if is_notify {
             if let Some(instance_warp) = self.instances.get(&key) {
                 let mut add_list = vec![];
+                let mut modify_list = vec![];
                 for item in &instance_warp.instances {
-                    let key = format!("{}:{}", item.ip, item.port);
-                    if old_instance_map.remove(&key).is_none() {
-                        add_list.push(item.clone());
+                    let key = item.get_ip_port();
+                    match old_instance_map.remove(&key) {
+                        Some(old) => {
+                            if old.is_modified(item) {
+                                modify_list.push(InstanceUpdate {
+                                    old,
+                                    new: item.clone(),
+                                });
+                            }
+                        }
+                        None => add_list.push(item.clone()),
                     }
                 }
                 let remove_list: Vec<Arc<Instance>> =
                     old_instance_map.into_iter().map(|(_, v)| v).collect();
-                self.notify_listener(key, &instance_warp.instances, add_list, remove_list);
+                let event = ServiceChangeEvent {
+                    key: ServiceInstanceKey::from_str(&key),
+                    instances: instance_warp.instances.clone(),
+                    add_list,
+                    remove_list,
+                    modify_list,
+                    service: instance_warp.service.clone(),
+                    metadata_changed,
+                };
+                //实例有变化时才更新磁盘缓存
+                if !from_cache && !event.is_empty() {
+                    if let Some(cache) = &self.cache {
+                        if let Err(e) = cache.write(&key, &result) {
+                            log::warn!("write instances cache error,key:{},{}", &key, e);
+                        }
+                    }
+                }
+                self.notify_listener(&key, &event);
             }
         }
-        Ok(())
     }
 
     fn update_instances_and_notify(
@@ -265,23 +475,10 @@ impl InnerNamingListener {
         key: String,
         result: QueryListResult,
     ) -> anyhow::Result<()> {
-        let service_result = Self::convert_to_service_result(result);
+        let service_result = result.to_service_result();
         self.update_instances_and_notify_by_service_result(key, service_result)
     }
 
-    fn convert_to_service_result(result: QueryListResult) -> ServiceResult {
-        let hosts = result
-            .hosts
-            .unwrap_or_default()
-            .into_iter()
-            .map(|e| Arc::new(e.to_instance()))
-            .collect();
-        ServiceResult {
-            cache_millis: result.cache_millis,
-            hosts,
-        }
-    }
-
     async fn send_conn_msg(
         conn_manage: Option<WeakAddr<ConnManage>>,
         request: NamingRequest,
@@ -309,29 +506,28 @@ impl InnerNamingListener {
         let response = response?;
         match response {
             NamingResponse::ServiceResult(r) => Ok(r),
-            NamingResponse::None => Err(anyhow::anyhow!("the naming response is none")),
+            _ => Err(anyhow::anyhow!("the naming response is none")),
         }
     }
 
-    fn notify_listener(
-        &self,
-        key_str: String,
-        instances: &Vec<Arc<Instance>>,
-        add_list: Vec<Arc<Instance>>,
-        remove_list: Vec<Arc<Instance>>,
-    ) {
-        if add_list.len() == 0 && remove_list.len() == 0 {
+    fn notify_listener(&self, key_str: &str, event: &ServiceChangeEvent) {
+        if event.is_empty() {
             return;
         }
-        let key = ServiceInstanceKey::from_str(&key_str);
-        if let Some(list) = self.listeners.get(&key_str) {
+        if let Some(list) = self.listeners.get(key_str) {
             for item in list {
-                item.listener
-                    .change(&key, instances, &add_list, &remove_list);
+                item.change(event);
             }
         }
     }
 
+    fn get_service_detail(&self, params: &QueryInstanceListParams) -> Arc<ServiceDetail> {
+        self.instances
+            .get(&params.get_key())
+            .map(|e| e.service.clone())
+            .unwrap_or_default()
+    }
+
     fn filter_instances(
         &mut self,
         params: &QueryInstanceListParams,
@@ -339,20 +535,7 @@ impl InnerNamingListener {
     ) -> Option<Vec<Arc<Instance>>> {
         let key = params.get_key();
         if let Some(instance_warp) = self.instances.get(&key) {
-            let mut list = vec![];
-            for item in &instance_warp.instances {
-                if params.healthy_only && !item.healthy {
-                    continue;
-                }
-                if let Some(clusters) = &params.clusters {
-                    let name = &item.cluster_name;
-                    if !clusters.contains(name) {
-                        continue;
-                    }
-                }
-                list.push(item.clone());
-            }
-            return Some(list);
+            return Some(params.filter_instances(&instance_warp.instances));
             //if list.len()> 0 {
             //    return Some(list);
             //}
@@ -384,6 +567,69 @@ impl InnerNamingListener {
         }
     }
 
+    fn add_listener(
+        &mut self,
+        key: ServiceInstanceKey,
+        id: u64,
+        selector: Option<LabelSelector>,
+        listener: Box<dyn InstanceListener + Send + 'static>,
+        always_notify: bool,
+        ctx: &mut actix::Context<Self>,
+    ) {
+        let key_str = key.get_key();
+        let listener_value = ListenerValue::new(key.clone(), listener, id, selector);
+        //如果已经存在，则直接触发一次
+        let (instances, service) = match self.instances.get(&key_str) {
+            Some(instance_wrap) => (
+                instance_wrap.instances.clone(),
+                instance_wrap.service.clone(),
+            ),
+            None => (vec![], Default::default()),
+        };
+        if !instances.is_empty() || always_notify {
+            let event = ServiceChangeEvent {
+                key: key.clone(),
+                instances: instances.clone(),
+                add_list: instances,
+                service,
+                ..Default::default()
+            };
+            listener_value.change(&event);
+        }
+        if let Some(list) = self.listeners.get_mut(&key_str) {
+            list.push(listener_value);
+        } else {
+            self.listeners.insert(key_str.clone(), vec![listener_value]);
+            let addr = ctx.address();
+            addr.do_send(NamingListenerCmd::AddHeartbeat(key));
+        }
+    }
+
+    /// 检查故障转移开关文件
+    fn check_failover(&self, ctx: &mut actix::Context<Self>) {
+        ctx.run_later(Duration::from_secs(5), |act, ctx| {
+            let failover = match &act.cache {
+                Some(cache) => cache.is_failover_switch_on(),
+                None => false,
+            };
+            if failover != act.failover {
+                act.failover = failover;
+                if failover {
+                    log::warn!("naming failover switch on");
+                    let keys: Vec<String> = act.instances.keys().cloned().collect();
+                    for key in keys {
+                        act.load_cache_instances(&key, true);
+                    }
+                } else {
+                    //http模式下次心跳会重新查询
+                    log::warn!("naming failover switch off");
+                    act.grpc_resubscribe();
+                }
+            }
+            act.check_failover(ctx);
+        });
+    }
+
     fn grpc_resubscribe(&mut self) {
         if !self.use_grpc {
             return;
@@ -393,10 +639,38 @@ impl InnerNamingListener {
             if key.service_name.is_empty() {
                 continue;
             }
-            let request = NamingRequest::Subscribe(vec![key]);
+            let request = NamingRequest::Subscribe(vec![self.with_namespace(key)]);
             Self::do_send_conn_msg(&self.conn_manage, request);
         }
     }
+
+    /// grpc 订阅时带上当前命名空间,多个命名空间可共享同一个连接
+    fn with_namespace(&self, mut key: ServiceInstanceKey) -> ServiceInstanceKey {
+        if !self.namespace_id.is_empty() {
+            key.namespace_id = Some(self.namespace_id.clone());
+        }
+        key
+    }
+
+    fn update_protocol(&mut self, use_grpc: bool, ctx: &mut actix::Context<Self>) {
+        if self.use_grpc == use_grpc {
+            return;
+        }
+        self.use_grpc = use_grpc;
+        self.timeout_set = Default::default();
+        if use_grpc {
+            self.grpc_resubscribe();
+            return;
+        }
+        self.init_udp_info(ctx);
+        //切换到 http 协议后立即查询一次
+        let current_time = now_millis();
+        let addr = ctx.address();
+        for (key, instance_warp) in self.instances.iter_mut() {
+            instance_warp.next_time = current_time;
+            addr.do_send(NamingListenerCmd::Heartbeat(key.to_owned(), current_time));
+        }
+    }
 }
 
 impl Actor for InnerNamingListener {
@@ -413,7 +687,10 @@ impl Actor for InnerNamingListener {
         }
         if !self.use_grpc {
             self.init_udp_info(ctx);
-            self.hb(ctx);
+        }
+        self.hb(ctx);
+        if self.cache.is_some() {
+            self.check_failover(ctx);
         }
     }
 }
@@ -426,11 +703,25 @@ pub enum NamingListenerCmd {
         u64,
         Box<dyn InstanceListener + Send + 'static>,
     ),
+    AddWithSelector(
+        ServiceInstanceKey,
+        u64,
+        LabelSelector,
+        Box<dyn InstanceListener + Send + 'static>,
+    ),
+    /// 添加监听,并立即通知一次当前的实例快照(列表为空也会通知)
+    Watch(
+        ServiceInstanceKey,
+        u64,
+        Box<dyn InstanceListener + Send + 'static>,
+    ),
     Remove(ServiceInstanceKey, u64),
     AddHeartbeat(ServiceInstanceKey),
     Heartbeat(String, u64),
     Close,
     GrpcResubscribe,
+    UpdateServerHosts(Vec<HostInfo>),
+    UpdateProtocol(bool),
 }
 
 impl Handler<NamingListenerCmd> for InnerNamingListener {
@@ -439,26 +730,13 @@ impl Handler<NamingListenerCmd> for InnerNamingListener {
     fn handle(&mut self, msg: NamingListenerCmd, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
             NamingListenerCmd::Add(key, id, listener) => {
-                let key_str = key.get_key();
-                //如果已经存在，则直接触发一次
-                if let Some(instance_wrap) = self.instances.get(&key_str) {
-                    if instance_wrap.instances.len() > 0 {
-                        listener.change(
-                            &key,
-                            &instance_wrap.instances,
-                            &instance_wrap.instances,
-                            &vec![],
-                        );
-                    }
-                }
-                let listener_value = ListenerValue::new(key.clone(), listener, id);
-                if let Some(list) = self.listeners.get_mut(&key_str) {
-                    list.push(listener_value);
-                } else {
-                    self.listeners.insert(key_str.clone(), vec![listener_value]);
-                    let addr = ctx.address();
-                    addr.do_send(NamingListenerCmd::AddHeartbeat(key));
-                }
+                self.add_listener(key, id, None, listener, false, ctx);
+            }
+            NamingListenerCmd::AddWithSelector(key, id, selector, listener) => {
+                self.add_listener(key, id, Some(selector), listener, false, ctx);
+            }
+            NamingListenerCmd::Watch(key, id, listener) => {
+                self.add_listener(key, id, None, listener, true, ctx);
             }
             NamingListenerCmd::AddHeartbeat(key) => {
                 let clone_key = key.clone();
@@ -471,14 +749,23 @@ impl Handler<NamingListenerCmd> for InnerNamingListener {
                     let mut instances = InstancesWrap::default();
                     instances.params.group_name = key.group_name;
                     instances.params.service_name = key.service_name;
+                    instances.params.clusters = key.clusters;
                     instances.params.namespace_id = self.namespace_id.to_owned();
                     instances.params.healthy_only = false;
                     instances.params.client_ip = Some(self.client_ip.clone());
                     instances.params.udp_port = Some(self.udp_port);
                     instances.next_time = current_time;
                     self.instances.insert(key_str.clone(), instances);
+                    let load_cache = match &self.cache {
+                        Some(cache) => cache.load_at_start || self.failover,
+                        None => false,
+                    };
+                    if load_cache {
+                        self.load_cache_instances(&key_str, false);
+                    }
                     if self.use_grpc {
-                        let request = NamingRequest::Subscribe(vec![clone_key]);
+                        let request =
+                            NamingRequest::Subscribe(vec![self.with_namespace(clone_key)]);
                         Self::do_send_conn_msg(&self.conn_manage, request)
                     } else {
                         let addr = ctx.address();
@@ -505,11 +792,14 @@ impl Handler<NamingListenerCmd> for InnerNamingListener {
                 }
                 if is_empty {
                     self.listeners.remove(&key_str);
-                    let request = NamingRequest::Unsubscribe(vec![key]);
+                    let request = NamingRequest::Unsubscribe(vec![self.with_namespace(key)]);
                     Self::do_send_conn_msg(&self.conn_manage, request)
                 }
             }
             NamingListenerCmd::Heartbeat(key, time) => {
+                if self.use_grpc {
+                    return Ok(());
+                }
                 let mut is_query = false;
                 if let Some(instance_warp) = self.instances.get_mut(&key) {
                     if instance_warp.next_time > time {
@@ -533,6 +823,13 @@ impl Handler<NamingListenerCmd> for InnerNamingListener {
             NamingListenerCmd::GrpcResubscribe => {
                 self.grpc_resubscribe();
             }
+            NamingListenerCmd::UpdateProtocol(use_grpc) => {
+                self.update_protocol(use_grpc, ctx);
+            }
+            NamingListenerCmd::UpdateServerHosts(hosts) => {
+                self.udp_addr
+                    .do_send(UdpWorkerCmd::UpdateServerHosts(hosts));
+            }
         };
         Ok(())
     }
@@ -545,11 +842,36 @@ impl Handler<UdpDataCmd> for InnerNamingListener {
             Some(data) => data,
             None => msg.data,
         };
-        let map: HashMap<String, String> = serde_json::from_slice(&data).unwrap_or_default();
-        if let Some(str_data) = map.get("data") {
-            let result: QueryListResult = serde_json::from_str(str_data)?;
-            let ref_time = result.last_ref_time.clone().unwrap_or_default();
-            let key = result.name.clone().unwrap_or_default();
+        let packet: PushPacket = match serde_json::from_slice(&data) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("ignore invalid udp data from {},{}", &msg.target_addr, e);
+                return Ok(());
+            }
+        };
+        if let Some(str_data) = &packet.data {
+            let result: QueryListResult = match serde_json::from_str(str_data) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("ignore invalid push data from {},{}", &msg.target_addr, e);
+                    return Ok(());
+                }
+            };
+            let ref_time = packet
+                .last_ref_time
+                .or(result.last_ref_time)
+                .unwrap_or_default();
+            //订阅指定集群时,推送数据中的clusters与订阅时一致
+            let key = match &result.clusters {
+                Some(clusters) if !clusters.is_empty() => {
+                    format!(
+                        "{}@@{}",
+                        result.name.as_deref().unwrap_or_default(),
+                        clusters
+                    )
+                }
+                _ => result.name.clone().unwrap_or_default(),
+            };
             //send to client
             let mut map = HashMap::new();
             map.insert("type", "push-ack".to_owned());
@@ -590,6 +912,8 @@ type ListenerSenderType = tokio::sync::oneshot::Sender<NamingQueryResult>;
 pub enum NamingQueryCmd {
     QueryList(QueryInstanceListParams, ListenerSenderType),
     Select(QueryInstanceListParams, ListenerSenderType),
+    /// 查询服务信息与满足条件的实例
+    QueryService(QueryInstanceListParams, ListenerSenderType),
     ChangeResult(ServiceInstanceKey, ServiceResult),
 }
 
@@ -597,6 +921,63 @@ pub enum NamingQueryResult {
     None,
     One(Arc<Instance>),
     List(Vec<Arc<Instance>>),
+    Service(Arc<ServiceDetail>, Vec<Arc<Instance>>),
+}
+
+type BuildQueryResultFn =
+    fn(&InnerNamingListener, &QueryInstanceListParams, Vec<Arc<Instance>>) -> NamingQueryResult;
+
+impl InnerNamingListener {
+    /// 本地有数据时直接返回;否则先向服务端查询并更新本地数据,查询失败时使用磁盘缓存
+    fn query_and_reply(
+        &mut self,
+        param: QueryInstanceListParams,
+        sender: ListenerSenderType,
+        ctx: &mut Context<Self>,
+        build: BuildQueryResultFn,
+    ) {
+        if let Some(list) = self.filter_instances(&param, ctx) {
+            sender.send(build(self, &param, list)).unwrap_or_default();
+            return;
+        }
+        let use_grpc = self.use_grpc;
+        let conn_manage = self.conn_manage.clone();
+        let service_key = param.build_key();
+        async move {
+            //http 请求也经过 ConnManage,使用当前可用的服务端地址
+            let request = if use_grpc {
+                NamingRequest::Subscribe(vec![service_key])
+            } else {
+                NamingRequest::QueryInstance(Box::new(param.clone()))
+            };
+            let res = Self::send_conn_msg(conn_manage, request).await;
+            let res = Self::convert_naming_response_to_service_result(res);
+            (res, sender, param)
+        }
+        .into_actor(self)
+        .map(move |(res, sender, param), act, ctx| {
+            let loaded = match res {
+                Ok(service_result) => {
+                    let key = param.get_key();
+                    act.update_instances_and_notify_by_service_result(key, service_result)
+                        .unwrap_or_default();
+                    true
+                }
+                Err(_) => act.load_cache_instances(&param.get_key(), false),
+            };
+            let list = if loaded {
+                act.filter_instances(&param, ctx)
+            } else {
+                None
+            };
+            let result = match list {
+                Some(list) => build(act, &param, list),
+                None => NamingQueryResult::None,
+            };
+            sender.send(result).unwrap_or_default();
+        })
+        .spawn(ctx);
+    }
 }
 
 impl Handler<NamingQueryCmd> for InnerNamingListener {
@@ -604,111 +985,30 @@ impl Handler<NamingQueryCmd> for InnerNamingListener {
     fn handle(&mut self, msg: NamingQueryCmd, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
             NamingQueryCmd::QueryList(param, sender) => {
-                if let Some(list) = self.filter_instances(&param, ctx) {
-                    sender
-                        .send(NamingQueryResult::List(list))
-                        .unwrap_or_default();
-                } else {
-                    let use_grpc = self.use_grpc;
-                    let conn_manage = self.conn_manage.clone();
-                    let service_key = param.build_key();
-                    let request_client = self.request_client.clone();
-                    async move {
-                        let res = if use_grpc {
-                            let request = NamingRequest::Subscribe(vec![service_key]);
-                            let grpc_res = Self::send_conn_msg(conn_manage, request).await;
-                            Self::convert_naming_response_to_service_result(grpc_res)
-                        } else {
-                            match request_client.get_instance_list(&param).await {
-                                Ok(result) => Ok(Self::convert_to_service_result(result)),
-                                Err(err) => Err(err),
-                            }
-                        };
-                        (res, sender, param)
-                    }
-                    .into_actor(self)
-                    .map(|(res, sender, param), act, ctx| {
-                        match res {
-                            Ok(service_result) => {
-                                let key = param.get_key();
-                                act.update_instances_and_notify_by_service_result(
-                                    key,
-                                    service_result,
-                                )
-                                .unwrap_or_default();
-                                if let Some(list) = act.filter_instances(&param, ctx) {
-                                    sender
-                                        .send(NamingQueryResult::List(list))
-                                        .unwrap_or_default();
-                                    return;
-                                }
-                            }
-                            Err(_) => {}
-                        }
-                        sender.send(NamingQueryResult::None).unwrap_or_default();
-                    })
-                    .spawn(ctx);
-                }
+                self.query_and_reply(param, sender, ctx, |_, _, list| {
+                    NamingQueryResult::List(list)
+                });
             }
             NamingQueryCmd::Select(param, sender) => {
-                if let Some(list) = self.filter_instances(&param, ctx) {
+                self.query_and_reply(param, sender, ctx, |_, _, list| {
                     let index =
                         NamingUtils::select_by_weight_fn(&list, |e| (e.weight * 1000f32) as u64);
-                    if let Some(e) = list.get(index) {
-                        sender
-                            .send(NamingQueryResult::One(e.clone()))
-                            .unwrap_or_default();
-                    } else {
-                        sender.send(NamingQueryResult::None).unwrap_or_default();
-                    }
-                } else {
-                    let use_grpc = self.use_grpc;
-                    let conn_manage = self.conn_manage.clone();
-                    let service_key = param.build_key();
-                    let request_client = self.request_client.clone();
-                    async move {
-                        let res = if use_grpc {
-                            let request = NamingRequest::Subscribe(vec![service_key]);
-                            let grpc_res = Self::send_conn_msg(conn_manage, request).await;
-                            Self::convert_naming_response_to_service_result(grpc_res)
-                        } else {
-                            match request_client.get_instance_list(&param).await {
-                                Ok(result) => Ok(Self::convert_to_service_result(result)),
-                                Err(err) => Err(err),
-                            }
-                        };
-                        (res, sender, param)
+                    match list.get(index) {
+                        Some(e) => NamingQueryResult::One(e.clone()),
+                        None => NamingQueryResult::None,
                     }
-                    .into_actor(self)
-                    .map(|(res, sender, param), act, ctx| {
-                        match res {
-                            Ok(service_result) => {
-                                let key = param.get_key();
-                                act.update_instances_and_notify_by_service_result(
-                                    key,
-                                    service_result,
-                                )
-                                .unwrap_or_default();
-                                if let Some(list) = act.filter_instances(&param, ctx) {
-                                    let index = NamingUtils::select_by_weight_fn(&list, |e| {
-                                        (e.weight * 1000f32) as u64
-                                    });
-                                    if let Some(e) = list.get(index) {
-                                        sender
-                                            .send(NamingQueryResult::One(e.clone()))
-                                            .unwrap_or_default();
-                                        return;
-                                    }
-                                }
-                            }
-                            Err(_) => {}
-                        }
-                        sender.send(NamingQueryResult::None).unwrap_or_default();
-                    })
-                    .spawn(ctx);
-                }
+                });
+            }
+            NamingQueryCmd::QueryService(param, sender) => {
+                self.query_and_reply(param, sender, ctx, |act, param, list| {
+                    NamingQueryResult::Service(act.get_service_detail(param), list)
+                });
             }
             NamingQueryCmd::ChangeResult(service_key, service_result) => {
+                //共享连接时会收到其它命名空间的推送
+                if !is_own_push(&self.namespace_id, &service_key) {
+                    return Ok(NamingQueryResult::None);
+                }
                 let key = service_key.get_key();
                 //println!("naming listener ChangeResult, {}",&key);
                 self.update_instances_and_notify_by_service_result(key, service_result)
@@ -718,3 +1018,40 @@ impl Handler<NamingQueryCmd> for InnerNamingListener {
         Ok(NamingQueryResult::None)
     }
 }
+
+/// 空命名空间即 public 命名空间
+fn is_same_namespace(a: &str, b: &str) -> bool {
+    a == b || (a.is_empty() && b == "public") || (a == "public" && b.is_empty())
+}
+
+/// 推送的服务是否属于当前命名空间;未带命名空间的视为 public
+fn is_own_push(namespace_id: &str, service_key: &ServiceInstanceKey) -> bool {
+    let push_namespace = service_key.namespace_id.as_deref().unwrap_or("");
+    is_same_namespace(push_namespace, namespace_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_own_push, is_same_namespace, ServiceInstanceKey};
+
+    #[test]
+    fn test_is_same_namespace() {
+        assert!(is_same_namespace("", "public"));
+        assert!(is_same_namespace("dev", "dev"));
+        assert!(!is_same_namespace("dev", "public"));
+        assert!(!is_same_namespace("", "dev"));
+    }
+
+    #[test]
+    fn test_is_own_push() {
+        let public_key = ServiceInstanceKey::new("foo", "DEFAULT_GROUP");
+        let mut dev_key = ServiceInstanceKey::new("foo", "DEFAULT_GROUP");
+        dev_key.new_with_namespace("dev");
+        //public 与 dev 两个命名空间共享连接时,只处理各自的推送
+        assert!(is_own_push("", &public_key));
+        assert!(is_own_push("public", &public_key));
+        assert!(!is_own_push("", &dev_key));
+        assert!(is_own_push("dev", &dev_key));
+        assert!(!is_own_push("dev", &public_key));
+    }
+}
@@ -1,31 +1,40 @@
 use std::sync::Arc;
 use std::time::Duration;
 //use actix::prelude::*;
+use crate::client::utils::Utils;
 use inner_mem_cache::TimeoutSet;
 use std::collections::HashMap;
 
 mod api_model;
+mod cache;
 mod client;
 mod listerner;
 mod register;
 mod request_client;
+mod selector;
 mod udp_actor;
+mod watch;
 
 pub use request_client::InnerNamingRequestClient;
 
+pub use cache::NamingCacheConfig;
+
 pub use api_model::{
-    BeatInfo, BeatRequest, InstanceVO, InstanceWebParams, InstanceWebQueryListParams, NamingUtils,
-    QueryListResult,
+    BeatInfo, BeatRequest, BeatResponse, InstanceVO, InstanceWebParams, InstanceWebQueryListParams,
+    NamingUtils, PushPacket, QueryListResult,
 };
 pub use client::NamingClient;
 pub use listerner::{
-    InnerNamingListener, InstanceDefaultListener, InstanceListener, NamingListenerCmd,
-    NamingQueryCmd, NamingQueryResult,
+    InnerNamingListener, InstanceDefaultListener, InstanceListener, InstanceUpdate,
+    NamingListenerCmd, NamingQueryCmd, NamingQueryResult, ServiceChangeEvent,
 };
-pub use register::{InnerNamingRegister, NamingRegisterCmd};
-pub use udp_actor::{UdpDataCmd, UdpWorker};
+pub use register::{DeregisterResult, InnerNamingRegister, NamingRegisterCmd};
+pub use selector::{LabelRequirement, LabelSelector};
+pub use udp_actor::{NamingUdpConfig, UdpDataCmd, UdpWorker};
+pub use watch::ServiceWatchStream;
 
 pub(crate) static REGISTER_PERIOD: u64 = 5000u64;
+pub(crate) static RECONCILE_PERIOD: u64 = 30000u64;
 
 #[derive(Debug, Clone, Default)]
 pub struct Instance {
@@ -102,6 +111,26 @@ impl Instance {
         )
     }
 
+    /// 权重是否大于0;权重为0的实例(如已摘流)默认不参与查询与选择
+    pub fn has_weight(&self) -> bool {
+        self.weight > 0.001f32
+    }
+
+    /// 判断同一实例(ip:port)的属性是否有变更
+    pub fn is_modified(&self, other: &Instance) -> bool {
+        self.weight != other.weight
+            || self.healthy != other.healthy
+            || self.enabled != other.enabled
+            || self.ephemeral != other.ephemeral
+            || self.cluster_name != other.cluster_name
+            || self.metadata != other.metadata
+    }
+
+    /// 实例地址,如 `127.0.0.1:8080`、`[::1]:8080`
+    pub fn get_ip_port(&self) -> String {
+        Utils::format_host_port(&self.ip, self.port)
+    }
+
     pub fn get_service_named(&self) -> String {
         format!("{}@@{}", self.group_name, self.service_name)
     }
@@ -131,6 +160,8 @@ impl Instance {
         req.service_name = beat.service_name;
         req.cluster_name = beat.cluster;
         req.group_name = self.group_name.to_owned();
+        req.ip = Some(beat.ip);
+        req.port = Some(beat.port);
         req
     }
 
@@ -139,6 +170,13 @@ impl Instance {
         serde_urlencoded::to_string(&req).unwrap()
     }
 
+    /// 轻量心跳只带实例标识,不带心跳详情
+    pub(crate) fn generate_light_beat_request_urlencode(&self) -> String {
+        let mut req = self.generate_beat_request();
+        req.beat = String::new();
+        serde_urlencoded::to_string(&req).unwrap()
+    }
+
     pub fn init_beat_string(&mut self) {
         self.beat_string = Some(Arc::new(self.generate_beat_request_urlencode()));
     }
@@ -167,6 +205,8 @@ pub struct ServiceInstanceKey {
     pub namespace_id: Option<String>,
     pub group_name: String,
     pub service_name: String,
+    /// 只订阅指定集群的实例;为空时订阅所有集群
+    pub clusters: Option<Vec<String>>,
 }
 
 impl ServiceInstanceKey {
@@ -178,12 +218,41 @@ impl ServiceInstanceKey {
         }
     }
 
+    pub fn new_with_clusters(service_name: &str, group_name: &str, clusters: Vec<String>) -> Self {
+        Self::new(service_name, group_name).with_clusters(clusters)
+    }
+
+    /// 只订阅指定集群的实例,集群列表为空时订阅所有集群
+    pub fn with_clusters(mut self, clusters: Vec<String>) -> Self {
+        self.clusters = if clusters.is_empty() {
+            None
+        } else {
+            Some(clusters)
+        };
+        self
+    }
+
     pub fn new_with_namespace(&mut self, namespace_id: &str) {
         self.namespace_id = Some(namespace_id.to_owned());
     }
 
+    /// 逗号分隔的集群列表,未指定集群时为空字符串
+    pub fn get_clusters_str(&self) -> String {
+        match &self.clusters {
+            Some(clusters) => clusters.join(","),
+            None => "".to_owned(),
+        }
+    }
+
+    /// group@@service,指定集群时为 group@@service@@cluster1,cluster2
     pub fn get_key(&self) -> String {
-        NamingUtils::get_group_and_service_name(&self.service_name, &self.group_name)
+        let key = NamingUtils::get_group_and_service_name(&self.service_name, &self.group_name);
+        let clusters = self.get_clusters_str();
+        if clusters.is_empty() {
+            key
+        } else {
+            format!("{}@@{}", key, clusters)
+        }
     }
 
     pub fn from_str(key_str: &str) -> Self {
@@ -192,10 +261,199 @@ impl ServiceInstanceKey {
             s.group_name = group;
             s.service_name = service;
         }
+        if let Some(clusters) = key_str.splitn(3, "@@").nth(2) {
+            if !clusters.is_empty() {
+                s.clusters = Some(clusters.split(',').map(|e| e.to_owned()).collect());
+            }
+        }
         s
     }
 }
 
+/// 服务级别的信息(不含实例列表)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceDetail {
+    /// 服务元数据;grpc协议推送的数据中不包含,为空
+    pub metadata: HashMap<String, String>,
+    pub clusters: Option<String>,
+    pub checksum: Option<String>,
+    /// 服务端最后刷新时间(毫秒)
+    pub last_ref_time: Option<i64>,
+}
+
+/// 实例亲和性配置(同可用区/同集群优先)
+#[derive(Debug, Clone, Default)]
+pub struct InstanceAffinity {
+    /// 匹配的元数据key;为空时匹配 Instance::cluster_name
+    pub metadata_key: Option<String>,
+    /// 本地(同可用区/同集群)的值
+    pub value: String,
+    /// 本地健康实例占比低于该阈值时,退化为使用所有实例
+    pub threshold: f32,
+}
+
+impl InstanceAffinity {
+    pub fn new_by_cluster(cluster_name: &str, threshold: f32) -> Self {
+        Self {
+            metadata_key: None,
+            value: cluster_name.to_owned(),
+            threshold,
+        }
+    }
+
+    pub fn new_by_metadata(metadata_key: &str, value: &str, threshold: f32) -> Self {
+        Self {
+            metadata_key: Some(metadata_key.to_owned()),
+            value: value.to_owned(),
+            threshold,
+        }
+    }
+
+    pub fn is_local(&self, instance: &Instance) -> bool {
+        match &self.metadata_key {
+            Some(metadata_key) => instance
+                .metadata
+                .as_ref()
+                .and_then(|m| m.get(metadata_key))
+                .map(|v| v == &self.value)
+                .unwrap_or(false),
+            None => instance.cluster_name == self.value,
+        }
+    }
+
+    /// 优先返回本地实例中满足 matches 的实例;本地健康实例占比按过滤前的全部实例计算,
+    /// 本地没有健康实例、健康占比低于阈值或本地没有满足条件的实例时,返回全部实例中满足 matches 的实例
+    pub fn filter<F>(&self, list: &[Arc<Instance>], matches: F) -> Vec<Arc<Instance>>
+    where
+        F: Fn(&Instance) -> bool,
+    {
+        let all_matches = || list.iter().filter(|e| matches(e)).cloned().collect();
+        let local_list: Vec<&Arc<Instance>> = list.iter().filter(|e| self.is_local(e)).collect();
+        let healthy_count = local_list.iter().filter(|e| e.healthy).count();
+        if healthy_count == 0 {
+            return all_matches();
+        }
+        if (healthy_count as f32) < (local_list.len() as f32) * self.threshold {
+            return all_matches();
+        }
+        let local_list: Vec<Arc<Instance>> = local_list
+            .into_iter()
+            .filter(|e| matches(e))
+            .cloned()
+            .collect();
+        if local_list.is_empty() {
+            return all_matches();
+        }
+        local_list
+    }
+}
+
+/// 实例列表更新保护配置,防止异常推送(推空、大量下线)影响调用方
+#[derive(Debug, Clone, Default)]
+pub struct InstanceProtectConfig {
+    /// 拒绝把非空列表更新为空列表
+    pub empty_protection: bool,
+    /// 单次更新最多允许移除的实例比例(0~1)
+    pub max_remove_rate: Option<f32>,
+    /// 未设置 max_remove_rate 时,使用服务端的 protect_threshold 作为保护阈值
+    pub use_server_threshold: bool,
+}
+
+impl InstanceProtectConfig {
+    pub fn new(empty_protection: bool, max_remove_rate: Option<f32>) -> Self {
+        Self {
+            empty_protection,
+            max_remove_rate,
+            use_server_threshold: false,
+        }
+    }
+
+    pub fn set_use_server_threshold(mut self, use_server_threshold: bool) -> Self {
+        self.use_server_threshold = use_server_threshold;
+        self
+    }
+
+    /// 检查是否应拒绝本次更新,返回拒绝原因
+    pub(crate) fn check(
+        &self,
+        old_list: &[Arc<Instance>],
+        new_list: &[Arc<Instance>],
+        server_protect_threshold: Option<f32>,
+    ) -> Option<String> {
+        if old_list.is_empty() {
+            return None;
+        }
+        if self.empty_protection && new_list.is_empty() {
+            return Some("the new instance list is empty".to_owned());
+        }
+        let max_remove_rate = match self.max_remove_rate {
+            Some(v) => v,
+            None => match server_protect_threshold {
+                Some(v) if self.use_server_threshold && v > 0f32 => 1f32 - v,
+                _ => return None,
+            },
+        };
+        let new_keys: std::collections::HashSet<String> =
+            new_list.iter().map(|e| e.get_ip_port()).collect();
+        let remove_count = old_list
+            .iter()
+            .filter(|e| !new_keys.contains(&e.get_ip_port()))
+            .count();
+        let remove_rate = remove_count as f32 / old_list.len() as f32;
+        if remove_rate > max_remove_rate {
+            return Some(format!(
+                "remove {} of {} instances, more than the max remove rate {}",
+                remove_count,
+                old_list.len(),
+                max_remove_rate
+            ));
+        }
+        None
+    }
+}
+
+/// 定期核对服务端的注册实例,重新注册缺失的实例
+#[derive(Debug, Clone)]
+pub struct InstanceReconcileConfig {
+    /// 核对间隔
+    pub period: Duration,
+    /// 服务端实例的权重、集群或元数据与本地不一致时也重新注册;
+    /// 会覆盖在控制台上的修改(如把权重设为0摘流),默认关闭
+    pub correct_drift: bool,
+}
+
+impl Default for InstanceReconcileConfig {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_millis(RECONCILE_PERIOD),
+            correct_drift: false,
+        }
+    }
+}
+
+impl InstanceReconcileConfig {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            correct_drift: false,
+        }
+    }
+
+    pub fn set_correct_drift(mut self, correct_drift: bool) -> Self {
+        self.correct_drift = correct_drift;
+        self
+    }
+}
+
+/// 创建 NamingClient 时监听器相关的可选配置
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NamingListenerOptions {
+    pub protect_config: InstanceProtectConfig,
+    pub cache_config: Option<NamingCacheConfig>,
+    pub udp_config: NamingUdpConfig,
+    pub reconcile_config: InstanceReconcileConfig,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct QueryInstanceListParams {
     pub namespace_id: String,
@@ -203,6 +461,10 @@ pub struct QueryInstanceListParams {
     pub service_name: String,
     pub clusters: Option<Vec<String>>,
     pub healthy_only: bool,
+    /// 是否包含权重为0的实例
+    pub include_zero_weight: bool,
+    pub affinity: Option<InstanceAffinity>,
+    pub selector: Option<LabelSelector>,
     client_ip: Option<String>,
     udp_port: Option<u16>,
 }
@@ -221,11 +483,29 @@ impl QueryInstanceListParams {
             service_name: service_name.to_owned(),
             clusters: clusters,
             healthy_only,
+            include_zero_weight: false,
+            affinity: None,
+            selector: None,
             client_ip: None,
             udp_port: None,
         }
     }
 
+    pub fn set_include_zero_weight(mut self, include_zero_weight: bool) -> Self {
+        self.include_zero_weight = include_zero_weight;
+        self
+    }
+
+    pub fn set_affinity(mut self, affinity: Option<InstanceAffinity>) -> Self {
+        self.affinity = affinity;
+        self
+    }
+
+    pub fn set_selector(mut self, selector: Option<LabelSelector>) -> Self {
+        self.selector = selector;
+        self
+    }
+
     pub fn new_simple(service_name: &str, group_name: &str) -> Self {
         Self::new("", group_name, service_name, None, true)
     }
@@ -238,11 +518,45 @@ impl QueryInstanceListParams {
         NamingUtils::get_group_and_service_name(&self.service_name, &self.group_name)
     }
 
+    /// 实例是否满足查询条件(不含亲和性)
+    pub fn matches_instance(&self, instance: &Instance) -> bool {
+        if self.healthy_only && !instance.healthy {
+            return false;
+        }
+        if !self.include_zero_weight && !instance.has_weight() {
+            return false;
+        }
+        if let Some(clusters) = &self.clusters {
+            if !clusters.contains(&instance.cluster_name) {
+                return false;
+            }
+        }
+        if let Some(selector) = &self.selector {
+            if !selector.matches_instance(instance) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 按查询条件过滤实例,设置了亲和性时优先返回本地实例
+    pub fn filter_instances(&self, list: &[Arc<Instance>]) -> Vec<Arc<Instance>> {
+        match &self.affinity {
+            Some(affinity) => affinity.filter(list, |e| self.matches_instance(e)),
+            None => list
+                .iter()
+                .filter(|e| self.matches_instance(e))
+                .cloned()
+                .collect(),
+        }
+    }
+
     pub fn build_key(&self) -> ServiceInstanceKey {
         ServiceInstanceKey {
             namespace_id: Some(self.namespace_id.clone()),
             group_name: self.group_name.clone(),
             service_name: self.service_name.clone(),
+            clusters: None,
         }
     }
 
@@ -261,3 +575,120 @@ impl QueryInstanceListParams {
         params
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        Instance, InstanceAffinity, InstanceProtectConfig, QueryInstanceListParams,
+        ServiceInstanceKey,
+    };
+
+    fn build_instance(ip: &str, cluster_name: &str, healthy: bool) -> Arc<Instance> {
+        let mut instance = Instance::new(ip, 8080, "foo", "", cluster_name, "", None);
+        instance.healthy = healthy;
+        Arc::new(instance)
+    }
+
+    #[test]
+    fn test_affinity_prefer_local_cluster() {
+        let list = vec![
+            build_instance("127.0.0.1", "A", true),
+            build_instance("127.0.0.2", "A", true),
+            build_instance("127.0.0.3", "B", true),
+        ];
+        let affinity = InstanceAffinity::new_by_cluster("A", 0.5);
+        let result = affinity.filter(&list, |_| true);
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|e| e.cluster_name == "A"));
+    }
+
+    #[test]
+    fn test_affinity_fallback_below_threshold() {
+        let list = vec![
+            build_instance("127.0.0.1", "A", true),
+            build_instance("127.0.0.2", "A", false),
+            build_instance("127.0.0.3", "A", false),
+            build_instance("127.0.0.4", "B", true),
+        ];
+        let affinity = InstanceAffinity::new_by_cluster("A", 0.5);
+        assert_eq!(affinity.filter(&list, |_| true).len(), 4);
+        let affinity = InstanceAffinity::new_by_cluster("C", 0.5);
+        assert_eq!(affinity.filter(&list, |_| true).len(), 4);
+    }
+
+    #[test]
+    fn test_affinity_fallback_with_healthy_only() {
+        let list = vec![
+            build_instance("127.0.0.1", "A", true),
+            build_instance("127.0.0.2", "A", false),
+            build_instance("127.0.0.3", "A", false),
+            build_instance("127.0.0.4", "B", true),
+        ];
+        //本地健康占比 1/3 低于阈值,退化为使用全部健康实例
+        let params = QueryInstanceListParams::new("", "", "foo", None, true)
+            .set_affinity(Some(InstanceAffinity::new_by_cluster("A", 0.5)));
+        let result = params.filter_instances(&list);
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|e| e.healthy));
+        //本地健康占比满足阈值时只返回本地健康实例
+        let params = params.set_affinity(Some(InstanceAffinity::new_by_cluster("A", 0.3)));
+        let result = params.filter_instances(&list);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].ip, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_protect_config_check() {
+        let old_list = vec![
+            build_instance("127.0.0.1", "A", true),
+            build_instance("127.0.0.2", "A", true),
+            build_instance("127.0.0.3", "A", true),
+            build_instance("127.0.0.4", "A", true),
+        ];
+        let config = InstanceProtectConfig::default();
+        assert!(config.check(&old_list, &[], Some(0.8)).is_none());
+        let config = InstanceProtectConfig::new(true, Some(0.5));
+        assert!(config.check(&old_list, &[], None).is_some());
+        assert!(config.check(&old_list, &old_list[..1], None).is_some());
+        assert!(config.check(&old_list, &old_list[..2], None).is_none());
+        assert!(config.check(&[], &[], None).is_none());
+        let config = InstanceProtectConfig::new(false, None).set_use_server_threshold(true);
+        assert!(config.check(&old_list, &old_list[..3], Some(0.8)).is_some());
+        assert!(config.check(&old_list, &old_list[..3], Some(0.7)).is_none());
+    }
+
+    #[test]
+    fn test_affinity_by_metadata() {
+        let mut instance = Instance::new_simple("127.0.0.1", 8080, "foo", "");
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert("zone".to_owned(), "z1".to_owned());
+        instance.metadata = Some(metadata);
+        let list = vec![Arc::new(instance), build_instance("127.0.0.2", "A", true)];
+        let affinity = InstanceAffinity::new_by_metadata("zone", "z1", 0.5);
+        let result = affinity.filter(&list, |_| true);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].ip, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_service_key_with_clusters() {
+        let key = ServiceInstanceKey::new("foo", "DEFAULT_GROUP");
+        assert_eq!(key.get_key(), "DEFAULT_GROUP@@foo");
+        assert!(ServiceInstanceKey::from_str(&key.get_key())
+            .clusters
+            .is_none());
+
+        let key = ServiceInstanceKey::new_with_clusters(
+            "foo",
+            "DEFAULT_GROUP",
+            vec!["A".to_owned(), "B".to_owned()],
+        );
+        assert_eq!(key.get_key(), "DEFAULT_GROUP@@foo@@A,B");
+        let parsed = ServiceInstanceKey::from_str(&key.get_key());
+        assert_eq!(parsed.service_name, "foo");
+        assert_eq!(parsed.group_name, "DEFAULT_GROUP");
+        assert_eq!(parsed.clusters, key.clusters);
+    }
+}
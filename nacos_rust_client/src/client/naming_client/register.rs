@@ -1,42 +1,159 @@
+use crate::client::naming_client::BeatResponse;
+use crate::client::naming_client::InstanceReconcileConfig;
+use crate::client::naming_client::QueryInstanceListParams;
 use crate::client::naming_client::REGISTER_PERIOD;
 use crate::client::now_millis;
 use crate::conn_manage::conn_msg::NamingRequest;
+use crate::conn_manage::conn_msg::NamingResponse;
 use crate::conn_manage::manage::ConnManage;
 use crate::conn_manage::manage::ConnManageCmd;
 use actix::prelude::*;
 use actix::WeakAddr;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
 //use crate::client::naming_client::InnerNamingRequestClient;
 use crate::client::naming_client::Instance;
 use crate::client::naming_client::TimeoutSet;
 
+//心跳返回实例不存在
+const RESOURCE_NOT_FOUND: i32 = 20404;
+
+type DrainSenderType = tokio::sync::oneshot::Sender<Vec<DeregisterResult>>;
+
+/// 服务端通过心跳返回值控制的心跳参数
+#[derive(Debug, Clone)]
+struct BeatState {
+    period: u64,
+    light_beat_enabled: bool,
+}
+
+/// 实例注销结果
+#[derive(Debug)]
+pub struct DeregisterResult {
+    pub instance: Instance,
+    pub result: anyhow::Result<()>,
+}
+
 //#[derive()]
 pub struct InnerNamingRegister {
     instances: HashMap<String, Instance>,
+    beat_states: HashMap<String, BeatState>,
     timeout_set: TimeoutSet<String>,
     conn_manage: Option<WeakAddr<ConnManage>>,
     period: u64,
     stop_remove_all: bool,
     use_grpc: bool,
+    draining: bool,
+    reconcile_config: InstanceReconcileConfig,
 }
 
 impl InnerNamingRegister {
     pub fn new(use_grpc: bool, conn_manage: Option<WeakAddr<ConnManage>>) -> Self {
         Self {
             instances: Default::default(),
+            beat_states: Default::default(),
             timeout_set: Default::default(),
             period: REGISTER_PERIOD,
             stop_remove_all: false,
             conn_manage,
             use_grpc,
+            draining: false,
+            reconcile_config: Default::default(),
         }
     }
 
-    pub fn hb(&self, ctx: &mut actix::Context<Self>) {
-        if self.use_grpc {
+    pub fn set_reconcile_config(mut self, reconcile_config: InstanceReconcileConfig) -> Self {
+        self.reconcile_config = reconcile_config;
+        self
+    }
+
+    /// 定期核对服务端的实例,重新注册缺失的实例;开启 correct_drift 时也重新注册与本地不一致的实例
+    fn reconcile(&self, ctx: &mut actix::Context<Self>) {
+        ctx.run_later(self.reconcile_config.period, |act, ctx| {
+            act.reconcile_instances(ctx);
+            act.reconcile(ctx);
+        });
+    }
+
+    fn reconcile_instances(&self, ctx: &mut actix::Context<Self>) {
+        if self.draining || self.instances.is_empty() {
             return;
         }
+        let mut service_map: HashMap<String, Vec<Instance>> = HashMap::new();
+        for instance in self.instances.values() {
+            let key = format!(
+                "{}#{}#{}",
+                &instance.namespace_id, &instance.group_name, &instance.service_name
+            );
+            service_map.entry(key).or_default().push(instance.clone());
+        }
+        let conn_manage = self.conn_manage.clone();
+        let correct_drift = self.reconcile_config.correct_drift;
+        async move {
+            let conn_manage = match conn_manage.as_ref().and_then(|e| e.upgrade()) {
+                Some(addr) => addr,
+                None => return vec![],
+            };
+            let mut list = vec![];
+            for instances in service_map.into_values() {
+                let first = &instances[0];
+                let params = QueryInstanceListParams::new(
+                    &first.namespace_id,
+                    &first.group_name,
+                    &first.service_name,
+                    None,
+                    false,
+                );
+                let request = NamingRequest::QueryInstance(Box::new(params));
+                let result = match conn_manage.send(request).await {
+                    Ok(Ok(NamingResponse::ServiceResult(result))) => result,
+                    _ => continue,
+                };
+                let remote_map: HashMap<String, &Instance> = result
+                    .hosts
+                    .iter()
+                    .map(|e| (e.get_ip_port(), e.as_ref()))
+                    .collect();
+                for instance in instances {
+                    match remote_map.get(&instance.get_ip_port()) {
+                        Some(remote) if !correct_drift || !Self::is_drifted(&instance, remote) => {}
+                        _ => list.push(instance),
+                    }
+                }
+            }
+            list
+        }
+        .into_actor(self)
+        .map(|list, act, _| {
+            if act.draining {
+                return;
+            }
+            for instance in list {
+                //核对期间可能已被注销
+                if act.instances.contains_key(&instance.generate_key()) {
+                    log::warn!(
+                        "reregister missing or drifted instance,{},{}",
+                        instance.get_ip_port(),
+                        &instance.service_name
+                    );
+                    act.register_instance(instance);
+                }
+            }
+        })
+        .spawn(ctx);
+    }
+
+    fn is_drifted(local: &Instance, remote: &Instance) -> bool {
+        let empty = HashMap::new();
+        (local.weight - remote.weight).abs() > 0.001f32
+            || local.cluster_name != remote.cluster_name
+            || local.metadata.as_ref().unwrap_or(&empty)
+                != remote.metadata.as_ref().unwrap_or(&empty)
+    }
+
+    pub fn hb(&self, ctx: &mut actix::Context<Self>) {
+        //grpc 协议下 timeout_set 为空;保持运行以便切换到 http 协议后继续心跳
         ctx.run_later(Duration::new(1, 0), |act, ctx| {
             let current_time = now_millis();
             let addr = ctx.address();
@@ -71,20 +188,141 @@ impl InnerNamingRegister {
         }
     }
 
-    fn heartbeat_instance(&self, instance: &Instance) {
-        if let Some(conn_manage) = &self.conn_manage {
-            if let Some(addr) = conn_manage.upgrade() {
-                addr.do_send(NamingRequest::V1Heartbeat(
-                    instance.beat_string.clone().unwrap_or_default(),
-                ));
+    fn get_beat_period(&self, key: &str) -> u64 {
+        match self.beat_states.get(key) {
+            Some(state) => state.period,
+            None => self.period,
+        }
+    }
+
+    fn heartbeat_instance(&self, key: String, instance: &Instance, ctx: &mut actix::Context<Self>) {
+        let light_beat_enabled = match self.beat_states.get(&key) {
+            Some(state) => state.light_beat_enabled,
+            None => false,
+        };
+        let beat_string = if light_beat_enabled {
+            Arc::new(instance.generate_light_beat_request_urlencode())
+        } else {
+            instance.beat_string.clone().unwrap_or_default()
+        };
+        let conn_manage = self.conn_manage.clone();
+        async move {
+            let request = NamingRequest::V1Heartbeat(beat_string);
+            let res = match conn_manage.as_ref().and_then(|e| e.upgrade()) {
+                Some(addr) => match addr.send(request).await {
+                    Ok(res) => res,
+                    Err(e) => Err(e.into()),
+                },
+                None => Err(anyhow::anyhow!("conn manage is empty")),
+            };
+            (key, res)
+        }
+        .into_actor(self)
+        .map(|(key, res), act, _| match res {
+            Ok(NamingResponse::V1BeatResult(res)) => act.update_beat_state(key, res),
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("instance heartbeat error,{},{}", &key, e);
             }
+        })
+        .spawn(ctx);
+    }
+
+    fn update_beat_state(&mut self, key: String, res: BeatResponse) {
+        let instance = match self.instances.get(&key) {
+            Some(instance) => instance,
+            None => return,
+        };
+        if res.code == Some(RESOURCE_NOT_FOUND) {
+            log::warn!("instance not found in server,reregister {}", &key);
+            self.register_instance(instance.clone());
+        }
+        let mut state = BeatState {
+            period: self.get_beat_period(&key),
+            light_beat_enabled: res.light_beat_enabled.unwrap_or(false),
+        };
+        if let Some(interval) = res.client_beat_interval {
+            if interval > 0 {
+                state.period = interval;
+            }
+        }
+        self.beat_states.insert(key, state);
+    }
+
+    /// 下线流程使用,http 接口返回内容不为 ok 时也视为失败
+    async fn send_request(
+        conn_manage: &Option<WeakAddr<ConnManage>>,
+        request: NamingRequest,
+    ) -> anyhow::Result<()> {
+        match conn_manage.as_ref().and_then(|e| e.upgrade()) {
+            Some(addr) => match addr.send(request).await?? {
+                NamingResponse::V1OperateResult(false) => {
+                    Err(anyhow::anyhow!("server response is not ok"))
+                }
+                _ => Ok(()),
+            },
+            None => Err(anyhow::anyhow!("conn manage is empty")),
         }
     }
 
+    /// 先把实例权重设为0并等待 grace,再注销全部实例,完成后停止actor
+    fn drain_and_shutdown(
+        &mut self,
+        grace: Duration,
+        sender: DrainSenderType,
+        ctx: &mut actix::Context<Self>,
+    ) {
+        self.draining = true;
+        for instance in self.instances.values_mut() {
+            instance.weight = 0f32;
+            instance.init_beat_string();
+        }
+        let instances: Vec<Instance> = self.instances.values().cloned().collect();
+        let conn_manage = self.conn_manage.clone();
+        async move {
+            for instance in &instances {
+                let request = NamingRequest::Register(instance.clone());
+                if let Err(e) = Self::send_request(&conn_manage, request).await {
+                    log::warn!("drain instance error,{},{}", instance.get_ip_port(), e);
+                }
+            }
+            tokio::time::sleep(grace).await;
+            instances
+        }
+        .into_actor(self)
+        .map(|instances, act, ctx| {
+            //注销前停止心跳,避免心跳把实例重新注册回去
+            act.instances.clear();
+            act.beat_states.clear();
+            let conn_manage = act.conn_manage.clone();
+            async move {
+                let mut results = Vec::with_capacity(instances.len());
+                for instance in instances {
+                    let request = NamingRequest::Unregister(instance.clone());
+                    let result = Self::send_request(&conn_manage, request).await;
+                    results.push(DeregisterResult { instance, result });
+                }
+                results
+            }
+            .into_actor(act)
+            .map(|results, act, ctx| {
+                sender.send(results).ok();
+                act.stop_remove_all = true;
+                ctx.stop();
+            })
+            .spawn(ctx);
+        })
+        .spawn(ctx);
+    }
+
     fn register_all_instances(&self) {
         if !self.use_grpc {
             return;
         }
+        self.do_register_all_instances();
+    }
+
+    fn do_register_all_instances(&self) {
         if let Some(conn_manage) = &self.conn_manage {
             if let Some(addr) = conn_manage.upgrade() {
                 for instance in self.instances.values() {
@@ -93,6 +331,25 @@ impl InnerNamingRegister {
             }
         }
     }
+
+    fn update_protocol(&mut self, use_grpc: bool) {
+        if self.use_grpc == use_grpc {
+            return;
+        }
+        self.use_grpc = use_grpc;
+        if self.draining {
+            return;
+        }
+        self.do_register_all_instances();
+        self.timeout_set = Default::default();
+        if !use_grpc {
+            let time = now_millis();
+            for key in self.instances.keys() {
+                self.timeout_set
+                    .add(time + self.get_beat_period(key), key.to_owned());
+            }
+        }
+    }
 }
 
 impl Actor for InnerNamingRegister {
@@ -108,6 +365,7 @@ impl Actor for InnerNamingRegister {
             }
         }
         self.hb(ctx);
+        self.reconcile(ctx);
     }
 
     fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
@@ -128,6 +386,8 @@ pub enum NamingRegisterCmd {
     Heartbeat(String, u64),
     Close,
     Reregister,
+    UpdateProtocol(bool),
+    DrainAndShutdown(Duration, DrainSenderType),
 }
 
 impl Handler<NamingRegisterCmd> for InnerNamingRegister {
@@ -136,6 +396,10 @@ impl Handler<NamingRegisterCmd> for InnerNamingRegister {
     fn handle(&mut self, msg: NamingRegisterCmd, ctx: &mut Context<Self>) -> Self::Result {
         match msg {
             NamingRegisterCmd::Register(mut instance) => {
+                if self.draining {
+                    log::warn!("ignore register instance when draining");
+                    return Ok(());
+                }
                 instance.init_beat_string();
                 let key = instance.generate_key();
                 if self.instances.contains_key(&key) {
@@ -151,6 +415,7 @@ impl Handler<NamingRegisterCmd> for InnerNamingRegister {
             }
             NamingRegisterCmd::Remove(instance) => {
                 let key = instance.generate_key();
+                self.beat_states.remove(&key);
                 if let Some(instance) = self.instances.remove(&key) {
                     // request unregister
                     self.remove_instance(instance, ctx);
@@ -162,8 +427,9 @@ impl Handler<NamingRegisterCmd> for InnerNamingRegister {
                     return Ok(());
                 }
                 if let Some(instance) = self.instances.get(&key) {
-                    self.heartbeat_instance(&instance);
-                    self.timeout_set.add(time + self.period, key);
+                    self.heartbeat_instance(key.clone(), instance, ctx);
+                    let period = self.get_beat_period(&key);
+                    self.timeout_set.add(time + period, key);
                 }
             }
             NamingRegisterCmd::Close => {
@@ -172,7 +438,35 @@ impl Handler<NamingRegisterCmd> for InnerNamingRegister {
             NamingRegisterCmd::Reregister => {
                 self.register_all_instances();
             }
+            NamingRegisterCmd::UpdateProtocol(use_grpc) => {
+                self.update_protocol(use_grpc);
+            }
+            NamingRegisterCmd::DrainAndShutdown(grace, sender) => {
+                self.drain_and_shutdown(grace, sender, ctx);
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::InnerNamingRegister;
+    use crate::client::naming_client::Instance;
+
+    #[test]
+    fn test_instance_drifted() {
+        let local = Instance::new_simple("127.0.0.1", 8080, "foo", "DEFAULT_GROUP");
+        let mut remote = local.clone();
+        remote.healthy = false;
+        remote.metadata = Some(HashMap::new());
+        assert!(!InnerNamingRegister::is_drifted(&local, &remote));
+        remote.weight = 0f32;
+        assert!(InnerNamingRegister::is_drifted(&local, &remote));
+        let mut remote = local.clone();
+        remote.metadata = Some(HashMap::from([("k".to_owned(), "v".to_owned())]));
+        assert!(InnerNamingRegister::is_drifted(&local, &remote));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::client::auth::{AuthActor, AuthCmd, AuthHandleResult};
+use crate::client::naming_client::BeatResponse;
 use crate::client::naming_client::Instance;
 use crate::client::naming_client::QueryInstanceListParams;
 use crate::client::naming_client::QueryListResult;
@@ -36,7 +37,7 @@ impl InnerNamingRequestClient {
     */
 
     pub(crate) fn new_with_endpoint(endpoints: Arc<ServerEndpointInfo>) -> Self {
-        let client = reqwest::Client::builder().build().unwrap();
+        let client = endpoints.http_client();
         let mut headers = HashMap::new();
         headers.insert(
             "Content-Type".to_owned(),
@@ -78,8 +79,9 @@ impl InnerNamingRequestClient {
         let host = self.endpoints.select_host();
         let token_param = self.get_token().await;
         let url = format!(
-            "http://{}:{}/nacos/v1/ns/instance?{}",
-            &host.ip, &host.port, token_param
+            "{}/nacos/v1/ns/instance?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
         let resp = Utils::request(
             &self.client,
@@ -87,7 +89,7 @@ impl InnerNamingRequestClient {
             &url,
             body.as_bytes().to_vec(),
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         //log::info!("register:{}",resp.get_lossy_string_body());
@@ -100,8 +102,9 @@ impl InnerNamingRequestClient {
         let host = self.endpoints.select_host();
         let token_param = self.get_token().await;
         let url = format!(
-            "http://{}:{}/nacos/v1/ns/instance?{}",
-            &host.ip, &host.port, token_param
+            "{}/nacos/v1/ns/instance?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
         let resp = Utils::request(
             &self.client,
@@ -109,19 +112,20 @@ impl InnerNamingRequestClient {
             &url,
             body.as_bytes().to_vec(),
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         //log::info!("remove:{}",resp.get_lossy_string_body());
         Ok("ok" == resp.get_string_body())
     }
 
-    pub(crate) async fn heartbeat(&self, beat_string: Arc<String>) -> anyhow::Result<bool> {
+    pub(crate) async fn heartbeat(&self, beat_string: Arc<String>) -> anyhow::Result<BeatResponse> {
         let host = self.endpoints.select_host();
         let token_param = self.get_token().await;
         let url = format!(
-            "http://{}:{}/nacos/v1/ns/instance/beat?{}",
-            &host.ip, &host.port, token_param
+            "{}/nacos/v1/ns/instance/beat?{}",
+            self.endpoints.get_server_url(host),
+            token_param
         );
         let resp = Utils::request(
             &self.client,
@@ -129,11 +133,19 @@ impl InnerNamingRequestClient {
             &url,
             beat_string.as_bytes().to_vec(),
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
         //log::debug!("heartbeat:{}",resp.get_lossy_string_body());
-        return Ok("ok" == resp.get_string_body());
+        let body = resp.get_string_body();
+        //旧版本服务端返回 ok
+        if "ok" == body {
+            return Ok(BeatResponse::default());
+        }
+        match serde_json::from_str::<BeatResponse>(&body) {
+            Ok(v) => Ok(v),
+            Err(_) => Err(anyhow::anyhow!("heartbeat response error:{}", body)),
+        }
     }
 
     pub(crate) async fn get_instance_list(
@@ -144,9 +156,8 @@ impl InnerNamingRequestClient {
         let token_param = self.get_token().await;
         let host = self.endpoints.select_host();
         let url = format!(
-            "http://{}:{}/nacos/v1/ns/instance/list?{}&{}",
-            &host.ip,
-            &host.port,
+            "{}/nacos/v1/ns/instance/list?{}&{}",
+            self.endpoints.get_server_url(host),
             token_param,
             &serde_urlencoded::to_string(&params)?
         );
@@ -156,7 +167,7 @@ impl InnerNamingRequestClient {
             &url,
             vec![],
             Some(&self.headers),
-            Some(3000),
+            Some(self.endpoints.options.request_timeout_millis()),
         )
         .await?;
 
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::client::utils::Utils;
+
+use super::Instance;
+
+/// 单个标签匹配条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelRequirement {
+    /// key=value,value 支持 `*` 通配
+    Equals(String, String),
+    /// key!=value,value 支持 `*` 通配;key不存在时也视为匹配
+    NotEquals(String, String),
+    /// key
+    Exists(String),
+    /// !key
+    NotExists(String),
+}
+
+impl LabelRequirement {
+    fn matches(&self, metadata: Option<&HashMap<String, String>>) -> bool {
+        let get = |key: &str| metadata.and_then(|m| m.get(key));
+        match self {
+            LabelRequirement::Equals(key, pattern) => match get(key) {
+                Some(v) => Utils::wildcard_match(pattern, v),
+                None => false,
+            },
+            LabelRequirement::NotEquals(key, pattern) => match get(key) {
+                Some(v) => !Utils::wildcard_match(pattern, v),
+                None => true,
+            },
+            LabelRequirement::Exists(key) => get(key).is_some(),
+            LabelRequirement::NotExists(key) => get(key).is_none(),
+        }
+    }
+}
+
+/// 基于实例元数据的标签选择器,如 `version=2.*,env!=staging`
+///
+/// 多个条件以 `,` 分隔,需同时满足。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector {
+    pub requirements: Vec<LabelRequirement>,
+}
+
+impl LabelSelector {
+    pub fn parse(expr: &str) -> anyhow::Result<Self> {
+        let mut requirements = vec![];
+        for item in expr.split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            let requirement = if let Some((key, value)) = item.split_once("!=") {
+                LabelRequirement::NotEquals(key.trim().to_owned(), value.trim().to_owned())
+            } else if let Some((key, value)) = item.split_once("==") {
+                LabelRequirement::Equals(key.trim().to_owned(), value.trim().to_owned())
+            } else if let Some((key, value)) = item.split_once('=') {
+                LabelRequirement::Equals(key.trim().to_owned(), value.trim().to_owned())
+            } else if let Some(key) = item.strip_prefix('!') {
+                LabelRequirement::NotExists(key.trim().to_owned())
+            } else {
+                LabelRequirement::Exists(item.to_owned())
+            };
+            let key = match &requirement {
+                LabelRequirement::Equals(key, _)
+                | LabelRequirement::NotEquals(key, _)
+                | LabelRequirement::Exists(key)
+                | LabelRequirement::NotExists(key) => key,
+            };
+            if key.is_empty() {
+                return Err(anyhow::anyhow!("invalid label selector:{}", expr));
+            }
+            requirements.push(requirement);
+        }
+        Ok(Self { requirements })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    pub fn matches(&self, metadata: Option<&HashMap<String, String>>) -> bool {
+        self.requirements.iter().all(|e| e.matches(metadata))
+    }
+
+    pub fn matches_instance(&self, instance: &Instance) -> bool {
+        self.matches(instance.metadata.as_ref())
+    }
+
+    pub fn filter(&self, list: &[Arc<Instance>]) -> Vec<Arc<Instance>> {
+        list.iter()
+            .filter(|e| self.matches_instance(e))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{LabelRequirement, LabelSelector};
+
+    fn build_metadata(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        let selector = LabelSelector::parse("version=2.*, env!=staging,canary,!debug").unwrap();
+        assert_eq!(
+            selector.requirements,
+            vec![
+                LabelRequirement::Equals("version".to_owned(), "2.*".to_owned()),
+                LabelRequirement::NotEquals("env".to_owned(), "staging".to_owned()),
+                LabelRequirement::Exists("canary".to_owned()),
+                LabelRequirement::NotExists("debug".to_owned()),
+            ]
+        );
+        assert!(LabelSelector::parse("=v1").is_err());
+        assert!(LabelSelector::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let selector = LabelSelector::parse("version=2.*,env!=staging").unwrap();
+        let metadata = build_metadata(&[("version", "2.1.0"), ("env", "prod")]);
+        assert!(selector.matches(Some(&metadata)));
+        let metadata = build_metadata(&[("version", "2.1.0"), ("env", "staging")]);
+        assert!(!selector.matches(Some(&metadata)));
+        let metadata = build_metadata(&[("version", "1.9")]);
+        assert!(!selector.matches(Some(&metadata)));
+        let metadata = build_metadata(&[("version", "2")]);
+        assert!(!selector.matches(Some(&metadata)));
+        assert!(!selector.matches(None));
+    }
+}
@@ -1,18 +1,45 @@
 use actix::prelude::*;
-use std::net::SocketAddr;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::UdpSocket;
 
 use super::InnerNamingListener;
+use crate::client::HostInfo;
 
 const MAX_DATAGRAM_SIZE: usize = 65_507;
+//服务端地址重新解析的间隔
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 接收 nacos 1.x udp 推送的配置
+#[derive(Debug, Clone, Default)]
+pub struct NamingUdpConfig {
+    /// 绑定地址,如 `0.0.0.0:0`、`192.168.1.10:50000`、`[::]:0`;默认 `0.0.0.0:0`
+    pub bind_addr: Option<String>,
+    /// 只接收来自已配置服务端地址的推送;默认关闭,
+    /// 通过 VIP/SLB 或解析到其它地址的域名访问服务端时不要开启
+    pub verify_source: bool,
+}
+
+impl NamingUdpConfig {
+    pub fn new(bind_addr: Option<String>, verify_source: bool) -> Self {
+        Self {
+            bind_addr,
+            verify_source,
+        }
+    }
+}
+
 pub struct UdpWorker {
     local_addr_str: Option<String>,
     socket: Option<Arc<UdpSocket>>,
     addr: Option<Addr<InnerNamingListener>>,
     udp_port: u16,
     buf: Option<Vec<u8>>,
+    verify_source: bool,
+    server_hosts: Vec<HostInfo>,
+    server_ips: HashSet<IpAddr>,
 }
 
 impl UdpWorker {
@@ -23,9 +50,20 @@ impl UdpWorker {
             addr,
             udp_port: 0,
             buf: Some(vec![]),
+            verify_source: false,
+            server_hosts: vec![],
+            server_ips: Default::default(),
         }
     }
 
+    /// 设置绑定地址及推送来源校验使用的服务端地址
+    pub fn set_config(mut self, config: NamingUdpConfig, server_hosts: Vec<HostInfo>) -> Self {
+        self.local_addr_str = config.bind_addr;
+        self.verify_source = config.verify_source;
+        self.server_hosts = server_hosts;
+        self
+    }
+
     pub fn new_with_socket(socket: UdpSocket, addr: Option<Addr<InnerNamingListener>>) -> Self {
         let local_addr = socket.local_addr().unwrap();
         let udp_port = local_addr.port();
@@ -35,14 +73,74 @@ impl UdpWorker {
             addr: addr,
             udp_port,
             buf: Some(vec![]),
+            verify_source: false,
+            server_hosts: vec![],
+            server_ips: Default::default(),
         }
     }
 
     fn init(&mut self, ctx: &mut actix::Context<Self>) {
+        if self.verify_source {
+            self.resolve_server_ips(ctx);
+        }
         self.init_socket(ctx);
         //self.init_loop_recv(ctx);
     }
 
+    fn resolve_server_ips(&mut self, ctx: &mut actix::Context<Self>) {
+        self.refresh_server_ips(ctx);
+        ctx.run_later(RESOLVE_INTERVAL, |act, ctx| {
+            act.resolve_server_ips(ctx);
+        });
+    }
+
+    fn refresh_server_ips(&mut self, ctx: &mut actix::Context<Self>) {
+        let hosts = self.server_hosts.clone();
+        async move {
+            let mut ips = HashSet::new();
+            for host in hosts {
+                match tokio::net::lookup_host((host.ip.as_str(), host.port as u16)).await {
+                    Ok(addrs) => {
+                        for addr in addrs {
+                            ips.insert(Self::normalize_ip(addr.ip()));
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("resolve nacos server host error,{},{}", &host.ip, e);
+                    }
+                }
+            }
+            ips
+        }
+        .into_actor(self)
+        .map(|ips, act, _| {
+            //解析失败时保留上一次的结果
+            if !ips.is_empty() {
+                act.server_ips = ips;
+            }
+        })
+        .spawn(ctx);
+    }
+
+    //双栈socket收到的ipv4地址为 ::ffff:a.b.c.d
+    fn normalize_ip(ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => ip,
+            },
+            IpAddr::V4(_) => ip,
+        }
+    }
+
+    fn is_trusted_source(&self, addr: &SocketAddr) -> bool {
+        //服务端地址还没有解析完成时不过滤
+        if !self.verify_source || self.server_ips.is_empty() {
+            return true;
+        }
+        self.server_ips.contains(&Self::normalize_ip(addr.ip()))
+    }
+
     fn init_socket(&mut self, ctx: &mut actix::Context<Self>) {
         if self.socket.is_some() {
             self.init_loop_recv(ctx);
@@ -53,55 +151,63 @@ impl UdpWorker {
         } else {
             "0.0.0.0:0".to_owned()
         };
-        async move { UdpSocket::bind(&local_addr_str).await.unwrap() }
+        async move { UdpSocket::bind(&local_addr_str).await }
             .into_actor(self)
             .map(|r, act, ctx| {
-                act.udp_port = r.local_addr().unwrap().port();
+                let socket = match r {
+                    Ok(socket) => socket,
+                    Err(e) => {
+                        log::error!("udp socket bind error,{}", e);
+                        return;
+                    }
+                };
+                act.udp_port = socket.local_addr().map(|e| e.port()).unwrap_or_default();
                 if let Some(_addr) = &act.addr {
                     _addr.do_send(InitLocalAddr { port: act.udp_port });
                 }
-                act.socket = Some(Arc::new(r));
+                act.socket = Some(Arc::new(socket));
                 act.init_loop_recv(ctx);
             })
             .wait(ctx);
     }
 
     fn init_loop_recv(&mut self, ctx: &mut actix::Context<Self>) {
-        let socket = self.socket.as_ref().unwrap().clone();
-        let notify_addr = self.addr.clone();
+        let socket = match &self.socket {
+            Some(socket) => socket.clone(),
+            None => return,
+        };
         let buf = self.buf.replace(Vec::new());
         async move {
             let mut buf = buf.unwrap_or_default();
             if buf.len() < MAX_DATAGRAM_SIZE {
                 buf = vec![0u8; MAX_DATAGRAM_SIZE];
             }
-            //let mut buf = buf.unwrap_or_else(|| vec![0u8; MAX_DATAGRAM_SIZE]);
-            //buf=vec![0u8;MAX_DATAGRAM_SIZE];
-            match socket.recv_from(&mut buf).await {
-                Ok((len, addr)) => {
-                    //let mut data:Vec<u8> = Vec::with_capacity(len);
-                    let mut data: Vec<u8> = vec![0u8; len];
-                    data.clone_from_slice(&buf[..len]);
-                    let msg = UdpDataCmd {
-                        data: data,
-                        target_addr: addr,
-                    };
-                    //let s=String::from_utf8_lossy(&buf[..len]);
-                    //println!("rece from:{} | len:{} | str:{}",&addr,len,s);
-                    if let Some(_notify_addr) = notify_addr {
-                        _notify_addr.do_send(msg);
-                    }
-                }
-                _ => {}
-            }
-            buf
+            let res = match socket.recv_from(&mut buf).await {
+                Ok((len, addr)) => Ok(UdpDataCmd::new(buf[..len].to_vec(), addr)),
+                Err(e) => Err(e),
+            };
+            (buf, res)
         }
         .into_actor(self)
-        .map(|buf, act, ctx| {
+        .map(|(buf, res), act, ctx| {
             act.buf.replace(buf);
-            ctx.run_later(Duration::new(1, 0), |act, ctx| {
-                act.init_loop_recv(ctx);
-            });
+            match res {
+                Ok(msg) => {
+                    if !act.is_trusted_source(&msg.target_addr) {
+                        log::warn!("ignore udp data from untrusted addr:{}", &msg.target_addr);
+                    } else if let Some(notify_addr) = &act.addr {
+                        notify_addr.do_send(msg);
+                    }
+                    act.init_loop_recv(ctx);
+                }
+                Err(e) => {
+                    //出错时稍后重试,避免空转
+                    log::warn!("udp recv error,{}", e);
+                    ctx.run_later(Duration::new(1, 0), |act, ctx| {
+                        act.init_loop_recv(ctx);
+                    });
+                }
+            }
         })
         .spawn(ctx);
     }
@@ -141,7 +247,10 @@ impl UdpDataCmd {
 impl Handler<UdpDataCmd> for UdpWorker {
     type Result = Result<(), std::io::Error>;
     fn handle(&mut self, msg: UdpDataCmd, ctx: &mut Context<Self>) -> Self::Result {
-        let socket = self.socket.as_ref().unwrap().clone();
+        let socket = match &self.socket {
+            Some(socket) => socket.clone(),
+            None => return Ok(()),
+        };
         async move {
             socket
                 .send_to(&msg.data, msg.target_addr)
@@ -160,6 +269,8 @@ impl Handler<UdpDataCmd> for UdpWorker {
 pub enum UdpWorkerCmd {
     QueryUdpPort,
     SetListenerAddr(Addr<InnerNamingListener>),
+    /// 服务端地址列表变更
+    UpdateServerHosts(Vec<HostInfo>),
     Close,
 }
 
@@ -190,7 +301,35 @@ impl Handler<UdpWorkerCmd> for UdpWorker {
             UdpWorkerCmd::SetListenerAddr(addr) => {
                 self.addr = Some(addr);
             }
+            UdpWorkerCmd::UpdateServerHosts(hosts) => {
+                self.server_hosts = hosts;
+                if self.verify_source {
+                    self.refresh_server_ips(ctx);
+                }
+            }
         };
         Ok(UdpWorkerResult::None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::UdpWorker;
+
+    #[test]
+    fn test_trusted_source() {
+        let mut worker = UdpWorker::new(None);
+        worker.verify_source = true;
+        let addr: SocketAddr = "10.0.0.2:52000".parse().unwrap();
+        assert!(worker.is_trusted_source(&addr));
+        worker.server_ips.insert("10.0.0.1".parse().unwrap());
+        let addr: SocketAddr = "10.0.0.1:52000".parse().unwrap();
+        assert!(worker.is_trusted_source(&addr));
+        let addr: SocketAddr = "[::ffff:10.0.0.1]:52000".parse().unwrap();
+        assert!(worker.is_trusted_source(&addr));
+        let addr: SocketAddr = "10.0.0.2:52000".parse().unwrap();
+        assert!(!worker.is_trusted_source(&addr));
+    }
+}
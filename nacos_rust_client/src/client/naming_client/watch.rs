@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::Poll;
+
+use actix::Addr;
+use futures_core::Stream;
+
+use super::{
+    InnerNamingListener, InstanceListener, NamingListenerCmd, QueryInstanceListParams,
+    ServiceChangeEvent, ServiceInstanceKey,
+};
+
+type EventSenderType = tokio::sync::mpsc::UnboundedSender<ServiceChangeEvent>;
+type EventReceiverType = tokio::sync::mpsc::UnboundedReceiver<ServiceChangeEvent>;
+
+/// 把实例变更事件转发到 ServiceWatchStream
+pub(crate) struct WatchListener {
+    key: ServiceInstanceKey,
+    params: QueryInstanceListParams,
+    sender: EventSenderType,
+    is_first: AtomicBool,
+}
+
+impl WatchListener {
+    pub(crate) fn new(
+        key: ServiceInstanceKey,
+        params: QueryInstanceListParams,
+        sender: EventSenderType,
+    ) -> Self {
+        Self {
+            key,
+            params,
+            sender,
+            is_first: AtomicBool::new(true),
+        }
+    }
+}
+
+impl InstanceListener for WatchListener {
+    fn get_key(&self) -> ServiceInstanceKey {
+        self.key.clone()
+    }
+
+    fn change_event(&self, event: &ServiceChangeEvent) {
+        let event = event.filter(|e| self.params.matches_instance(e));
+        //第一个事件是当前快照,为空也需要通知
+        if self.is_first.swap(false, Ordering::Relaxed) || !event.is_empty() {
+            self.sender.send(event).ok();
+        }
+    }
+}
+
+/// 服务实例变更流;第一个元素为当前实例快照,之后为增量变更。
+///
+/// drop 时自动取消监听。
+pub struct ServiceWatchStream {
+    key: ServiceInstanceKey,
+    id: u64,
+    receiver: EventReceiverType,
+    listener_addr: Addr<InnerNamingListener>,
+}
+
+impl ServiceWatchStream {
+    pub(crate) fn new(
+        key: ServiceInstanceKey,
+        id: u64,
+        receiver: EventReceiverType,
+        listener_addr: Addr<InnerNamingListener>,
+    ) -> Self {
+        Self {
+            key,
+            id,
+            receiver,
+            listener_addr,
+        }
+    }
+}
+
+impl Stream for ServiceWatchStream {
+    type Item = ServiceChangeEvent;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for ServiceWatchStream {
+    fn drop(&mut self) {
+        self.listener_addr
+            .do_send(NamingListenerCmd::Remove(self.key.clone(), self.id));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+use tonic::transport::{Channel, Uri};
+
+use super::HostInfo;
+
+/// 与 nacos 服务端通信的 TLS 配置,同时作用于 grpc 与 http 请求
+///
+/// 未设置 CA 证书时使用内置的 webpki 根证书;设置客户端证书与私钥后开启双向认证。
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM 格式的 CA 证书文件
+    pub ca_file: Option<PathBuf>,
+    /// PEM 格式的客户端证书文件
+    pub client_cert_file: Option<PathBuf>,
+    /// PEM 格式的客户端私钥文件
+    pub client_key_file: Option<PathBuf>,
+    /// 校验服务端证书时使用的域名,默认为连接地址
+    pub server_name: Option<String>,
+    /// 不校验服务端证书,仅用于开发测试
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_ca_file(mut self, ca_file: impl Into<PathBuf>) -> Self {
+        self.ca_file = Some(ca_file.into());
+        self
+    }
+
+    pub fn set_client_cert(
+        mut self,
+        cert_file: impl Into<PathBuf>,
+        key_file: impl Into<PathBuf>,
+    ) -> Self {
+        self.client_cert_file = Some(cert_file.into());
+        self.client_key_file = Some(key_file.into());
+        self
+    }
+
+    pub fn set_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = Some(server_name.to_owned());
+        self
+    }
+
+    pub fn set_insecure_skip_verify(mut self, insecure_skip_verify: bool) -> Self {
+        self.insecure_skip_verify = insecure_skip_verify;
+        self
+    }
+
+    pub(crate) fn build_client_config(&self, alpn: &[&[u8]]) -> anyhow::Result<ClientConfig> {
+        let roots = self.load_root_store()?;
+        let verifier: Arc<dyn ServerCertVerifier> = if self.insecure_skip_verify {
+            Arc::new(InsecureVerifier)
+        } else {
+            let server_name = match &self.server_name {
+                Some(v) => Some(ServerName::try_from(v.as_str())?),
+                None => None,
+            };
+            Arc::new(NacosServerVerifier {
+                inner: WebPkiVerifier::new(roots.clone(), None),
+                server_name,
+            })
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut config = match (&self.client_cert_file, &self.client_key_file) {
+            (Some(cert_file), Some(key_file)) => builder
+                .with_client_auth_cert(Self::load_certs(cert_file)?, Self::load_key(key_file)?)?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "client cert and key must be set at the same time"
+                ))
+            }
+        };
+        config.dangerous().set_certificate_verifier(verifier);
+        config.alpn_protocols = alpn.iter().map(|e| e.to_vec()).collect();
+        Ok(config)
+    }
+
+    fn load_root_store(&self) -> anyhow::Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca_file {
+            Some(ca_file) => {
+                for cert in Self::load_certs(ca_file)? {
+                    roots.add(&cert)?;
+                }
+            }
+            None => {
+                roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                }));
+            }
+        }
+        Ok(roots)
+    }
+
+    fn load_certs(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        let certs = rustls_pemfile::certs(&mut reader)?;
+        if certs.is_empty() {
+            return Err(anyhow::anyhow!("no certificate found in {:?}", path));
+        }
+        Ok(certs.into_iter().map(Certificate).collect())
+    }
+
+    fn load_key(path: &Path) -> anyhow::Result<PrivateKey> {
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        for item in rustls_pemfile::read_all(&mut reader)? {
+            match item {
+                rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+                _ => {}
+            }
+        }
+        Err(anyhow::anyhow!("no private key found in {:?}", path))
+    }
+}
+
+/// 创建连接时按 TlsConfig 加载证书后的 http 客户端与 grpc 配置,运行期间不再读取证书文件
+#[derive(Debug, Clone)]
+pub(crate) struct TlsClient {
+    http_client: reqwest::Client,
+    grpc_config: Arc<ClientConfig>,
+    server_name: Option<ServerName>,
+}
+
+impl TlsClient {
+    pub fn new(tls: &TlsConfig) -> anyhow::Result<Self> {
+        let config = tls.build_client_config(&[])?;
+        let mut http_config = config.clone();
+        http_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let http_client = reqwest::Client::builder()
+            .use_preconfigured_tls(http_config)
+            .build()?;
+        //http2 需要通过 alpn 协商
+        let mut grpc_config = config;
+        grpc_config.alpn_protocols = vec![b"h2".to_vec()];
+        let server_name = match &tls.server_name {
+            Some(v) => Some(ServerName::try_from(v.as_str())?),
+            None => None,
+        };
+        Ok(Self {
+            http_client,
+            grpc_config: Arc::new(grpc_config),
+            server_name,
+        })
+    }
+
+    pub fn http_client(&self) -> reqwest::Client {
+        self.http_client.clone()
+    }
+
+    /// 建立 tls 的 grpc 连接
+    pub fn build_grpc_channel(&self, host: &HostInfo) -> anyhow::Result<Channel> {
+        let server_name = match &self.server_name {
+            Some(v) => v.clone(),
+            None => ServerName::try_from(host.ip.as_str())?,
+        };
+        let connector = tokio_rustls::TlsConnector::from(self.grpc_config.clone());
+        let addr = host.get_grpc_addr();
+        let endpoint = Channel::from_shared(format!("http://{}", &addr))?;
+        let channel = endpoint.connect_with_connector_lazy(tower::service_fn(move |_: Uri| {
+            let connector = connector.clone();
+            let server_name = server_name.clone();
+            let addr = addr.clone();
+            async move {
+                let stream = tokio::net::TcpStream::connect(addr.as_str()).await?;
+                stream.set_nodelay(true)?;
+                connector.connect(server_name, stream).await
+            }
+        }));
+        Ok(channel)
+    }
+}
+
+/// 校验服务端证书,设置了 server_name 时按该域名校验
+struct NacosServerVerifier {
+    inner: WebPkiVerifier,
+    server_name: Option<ServerName>,
+}
+
+impl ServerCertVerifier for NacosServerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let server_name = self.server_name.as_ref().unwrap_or(server_name);
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+struct InsecureVerifier;
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{ClientBuilder, ServerEndpointInfo};
+
+    use super::TlsConfig;
+
+    #[test]
+    fn test_build_client_config() {
+        let config = TlsConfig::new()
+            .set_server_name("nacos.example.com")
+            .build_client_config(&[b"h2"])
+            .unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec()]);
+        assert!(TlsConfig::new()
+            .set_insecure_skip_verify(true)
+            .build_client_config(&[])
+            .is_ok());
+
+        let mut config = TlsConfig::new();
+        config.client_cert_file = Some("client.pem".into());
+        assert!(config.build_client_config(&[]).is_err());
+        assert!(TlsConfig::new()
+            .set_ca_file("/not/exists/ca.pem")
+            .build_client_config(&[])
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_http_client_error() {
+        let tls = TlsConfig::new().set_ca_file("/not/exists/ca.pem");
+        assert!(ServerEndpointInfo::from_hosts(vec![])
+            .set_tls_config(Some(tls.clone()))
+            .is_err());
+        assert!(ClientBuilder::new()
+            .set_tls_config(tls)
+            .build_connection()
+            .is_err());
+    }
+}
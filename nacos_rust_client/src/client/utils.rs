@@ -43,6 +43,41 @@ impl ResponseWrap {
 }
 
 impl Utils {
+    /// 简单通配匹配,`*` 匹配任意字符
+    pub fn wildcard_match(pattern: &str, value: &str) -> bool {
+        if !pattern.contains('*') {
+            return pattern == value;
+        }
+        let parts = pattern.split('*').collect::<Vec<_>>();
+        let last_index = parts.len() - 1;
+        let mut rest = value;
+        for (i, part) in parts.iter().enumerate() {
+            if i == 0 {
+                match rest.strip_prefix(part) {
+                    Some(v) => rest = v,
+                    None => return false,
+                }
+            } else if i == last_index {
+                return rest.ends_with(part);
+            } else {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+
+    /// 拼接地址与端口,ipv6 地址使用中括号包裹,如 `[::1]:8848`
+    pub fn format_host_port(host: &str, port: u32) -> String {
+        if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        }
+    }
+
     async fn get_response_wrap(resp: reqwest::Response) -> anyhow::Result<ResponseWrap> {
         let status = resp.status().as_u16();
         let mut resp_headers = vec![];
@@ -120,3 +155,17 @@ impl Utils {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Utils;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(Utils::wildcard_match("*", ""));
+        assert!(Utils::wildcard_match("a*c", "abbc"));
+        assert!(Utils::wildcard_match("*b*", "abc"));
+        assert!(!Utils::wildcard_match("a*c", "abcd"));
+        assert!(!Utils::wildcard_match("abc", "ab"));
+    }
+}
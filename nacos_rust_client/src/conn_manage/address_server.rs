@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use crate::client::{utils::Utils, HostInfo};
+
+/// 地址服务器配置
+///
+/// 定时从地址服务器拉取 nacos 服务端地址列表(每行一个 `ip:port`),服务端节点变更时不需要重启客户端。
+#[derive(Debug, Clone)]
+pub struct AddressServerConfig {
+    /// 地址列表url;不以 `http` 开头时按 `http://{url}/nacos/serverlist` 请求
+    pub url: String,
+    pub refresh_interval: Duration,
+}
+
+impl AddressServerConfig {
+    pub fn new(url: &str, refresh_interval: Duration) -> Self {
+        Self {
+            url: url.to_owned(),
+            refresh_interval,
+        }
+    }
+
+    pub(crate) fn get_server_list_url(&self) -> String {
+        let url = self.url.trim();
+        if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_owned()
+        } else {
+            format!("http://{}/nacos/serverlist", url.trim_end_matches('/'))
+        }
+    }
+
+    pub(crate) async fn fetch_hosts(
+        &self,
+        client: &reqwest::Client,
+        timeout_millis: u64,
+    ) -> anyhow::Result<Vec<HostInfo>> {
+        let url = self.get_server_list_url();
+        let resp = Utils::request(client, "GET", &url, vec![], None, Some(timeout_millis)).await?;
+        if !resp.status_is_200() {
+            return Err(anyhow::anyhow!(
+                "get server list error,status:{},url:{}",
+                resp.status,
+                &url
+            ));
+        }
+        let hosts = Self::parse_server_list(&resp.get_lossy_string_body());
+        if hosts.is_empty() {
+            return Err(anyhow::anyhow!("server list is empty,url:{}", &url));
+        }
+        Ok(hosts)
+    }
+
+    fn parse_server_list(content: &str) -> Vec<HostInfo> {
+        let mut hosts: Vec<HostInfo> = vec![];
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let host = HostInfo::parse(line);
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        hosts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::AddressServerConfig;
+
+    #[test]
+    fn test_parse_server_list() {
+        let config = AddressServerConfig::new("jmenv.example.com:8080", Duration::from_secs(30));
+        assert_eq!(
+            config.get_server_list_url(),
+            "http://jmenv.example.com:8080/nacos/serverlist"
+        );
+        let hosts = AddressServerConfig::parse_server_list(
+            "192.168.1.1:8848\n\n 192.168.1.2:8848 \r\n192.168.1.1:8848\n[::1]:8848\n",
+        );
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts[1].get_addr(), "192.168.1.2:8848");
+        assert_eq!(hosts[2].get_grpc_addr(), "[::1]:9848");
+    }
+}
@@ -1,13 +1,15 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use actix::prelude::*;
 
 use crate::client::{
     config_client::ConfigKey,
-    naming_client::{Instance, QueryInstanceListParams, ServiceInstanceKey},
+    naming_client::{
+        BeatResponse, Instance, QueryInstanceListParams, ServiceDetail, ServiceInstanceKey,
+    },
 };
 
-#[derive(Debug, Message)]
+#[derive(Debug, Clone, Message)]
 #[rtype(result = "anyhow::Result<ConfigResponse>")]
 pub enum ConfigRequest {
     GetConfig(ConfigKey),
@@ -24,7 +26,7 @@ pub enum ConfigResponse {
     None,
 }
 
-#[derive(Debug, Message)]
+#[derive(Debug, Clone, Message)]
 #[rtype(result = "anyhow::Result<NamingResponse>")]
 pub enum NamingRequest {
     Register(Instance),
@@ -40,11 +42,31 @@ pub enum NamingRequest {
 pub struct ServiceResult {
     pub hosts: Vec<Arc<Instance>>,
     pub cache_millis: Option<u64>,
+    pub protect_threshold: Option<f32>,
+    /// 服务元数据,grpc协议返回的数据中不包含
+    pub metadata: Option<HashMap<String, String>>,
+    pub clusters: Option<String>,
+    pub checksum: Option<String>,
+    pub last_ref_time: Option<i64>,
+}
+
+impl ServiceResult {
+    pub fn get_detail(&self) -> ServiceDetail {
+        ServiceDetail {
+            metadata: self.metadata.clone().unwrap_or_default(),
+            clusters: self.clusters.clone(),
+            checksum: self.checksum.clone(),
+            last_ref_time: self.last_ref_time,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum NamingResponse {
     ServiceResult(ServiceResult),
+    V1BeatResult(BeatResponse),
+    /// http 注册、注销接口的返回内容是否为 ok
+    V1OperateResult(bool),
     None,
 }
 
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::client::HostInfo;
+
+/// 客户端与 nacos 服务端的连接状态
+///
+/// 通过 `ConfigClient::connection_state` 或 `NamingClient::connection_state` 获取的 watch 通道订阅;
+/// http 模式没有长连接,选定服务端地址后即视为已连接。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnState {
+    /// 未连接或连接已断开
+    Disconnected,
+    /// 已连接到 server
+    Connected { server: HostInfo },
+    /// 第 attempt 次重连
+    Reconnecting { attempt: u32 },
+    /// 从 from 切换到 to,切换后还会通知 Connected
+    Failover { from: HostInfo, to: HostInfo },
+}
+
+impl ConnState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnState::Connected { .. })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConnStateNotifier {
+    sender: Arc<watch::Sender<ConnState>>,
+    receiver: watch::Receiver<ConnState>,
+}
+
+impl Default for ConnStateNotifier {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(ConnState::Disconnected);
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+}
+
+impl ConnStateNotifier {
+    pub fn subscribe(&self) -> watch::Receiver<ConnState> {
+        self.receiver.clone()
+    }
+
+    /// 与当前状态相同时不通知
+    pub fn notify(&self, state: ConnState) {
+        self.sender.send_if_modified(|current| {
+            if *current == state {
+                false
+            } else {
+                log::info!("nacos connection state change,{:?}", &state);
+                *current = state;
+                true
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::HostInfo;
+
+    use super::{ConnState, ConnStateNotifier};
+
+    #[test]
+    fn test_conn_state_notify() {
+        let notifier = ConnStateNotifier::default();
+        let mut receiver = notifier.subscribe();
+        assert_eq!(*receiver.borrow(), ConnState::Disconnected);
+        notifier.notify(ConnState::Disconnected);
+        assert!(!receiver.has_changed().unwrap());
+        let server = HostInfo::new("127.0.0.1", 8848);
+        notifier.notify(ConnState::Connected { server });
+        assert!(receiver.has_changed().unwrap());
+        assert!(receiver.borrow_and_update().is_connected());
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use actix::{Actor, Addr, WeakAddr};
 use tonic::transport::Channel;
@@ -6,14 +6,19 @@ use tonic::transport::Channel;
 use crate::{
     client::{
         config_client::inner_client::ConfigInnerRequestClient,
-        naming_client::InnerNamingRequestClient, ClientInfo, HostInfo,
+        naming_client::InnerNamingRequestClient, ClientInfo, HostInfo, TlsClient,
+    },
+    grpc::{
+        ability::ServerAbilities,
+        grpc_client::{InnerGrpcClient, InnerGrpcClientCmd},
     },
-    grpc::grpc_client::InnerGrpcClient,
 };
 
 use super::{
     breaker::{Breaker, BreakerConfig},
+    conn_msg::NamingRequest,
     manage::ConnManage,
+    options::ConnectionOptions,
 };
 
 #[derive(Default, Clone)]
@@ -28,6 +33,7 @@ pub(crate) struct InnerConn {
     pub grpc_client_addr: Option<Addr<InnerGrpcClient>>,
     pub config_request_client: Option<Arc<ConfigInnerRequestClient>>,
     pub naming_request_client: Option<Arc<InnerNamingRequestClient>>,
+    pub server_abilities: Arc<ServerAbilities>,
     client_info: Arc<ClientInfo>,
 }
 
@@ -50,33 +56,65 @@ impl InnerConn {
             manage_addr: None,
             config_request_client: None,
             naming_request_client: None,
+            server_abilities: Default::default(),
             client_info,
         }
     }
 
-    pub fn init_grpc(&mut self, manage_addr: WeakAddr<ConnManage>) -> anyhow::Result<()> {
+    pub fn build_grpc_channel(&self, tls: &Option<TlsClient>) -> anyhow::Result<Channel> {
+        let channel = match tls {
+            Some(tls) => tls.build_grpc_channel(&self.host_info)?,
+            None => {
+                let addr = format!("http://{}", self.host_info.get_grpc_addr());
+                Channel::from_shared(addr)?.connect_lazy()
+            }
+        };
+        Ok(channel)
+    }
+
+    pub fn init_grpc(
+        &mut self,
+        manage_addr: WeakAddr<ConnManage>,
+        tls: &Option<TlsClient>,
+        options: &ConnectionOptions,
+    ) -> anyhow::Result<()> {
         if self.support_grpc {
-            let addr = format!(
-                "http://{}:{}",
-                &self.host_info.ip, &self.host_info.grpc_port
-            );
-            let channel = Channel::from_shared(addr)?.connect_lazy();
+            let channel = self.build_grpc_channel(tls)?;
             let grpc_client = InnerGrpcClient::new_by_channel(
                 self.id.to_owned(),
                 channel.clone(),
                 manage_addr,
                 self.client_info.clone(),
-            )?;
+            )?
+            .set_connection_options(options);
             self.channel = Some(channel);
+            self.server_abilities = Default::default();
             self.grpc_client_addr = Some(grpc_client.start());
         }
         Ok(())
     }
 
-    pub fn close_grpc(&mut self) -> anyhow::Result<()> {
+    /// grpc 连接是否能处理该请求;服务端不支持通过 grpc 注册持久化实例时需要使用 http 请求,
+    /// 切换协议前已发出的 http 心跳也使用 http 请求
+    pub fn grpc_support_naming_request(&self, request: &NamingRequest) -> bool {
+        match request {
+            NamingRequest::V1Heartbeat(_) => false,
+            NamingRequest::Register(instance) | NamingRequest::Unregister(instance)
+                if !instance.ephemeral =>
+            {
+                self.server_abilities.support_persistent_instance_by_grpc()
+            }
+            _ => true,
+        }
+    }
+
+    /// 关闭grpc连接,grace 为等待处理中请求完成的时间
+    pub fn close_grpc(&mut self, grace: Duration) -> anyhow::Result<()> {
         if self.support_grpc {
             self.channel = None;
-            self.grpc_client_addr = None;
+            if let Some(addr) = self.grpc_client_addr.take() {
+                addr.do_send(InnerGrpcClientCmd::Close(grace));
+            }
         }
         Ok(())
     }
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use actix::{prelude::*, WeakAddr};
 
@@ -15,22 +19,29 @@ use crate::{
             InnerNamingListener, InnerNamingRegister, InnerNamingRequestClient, NamingListenerCmd,
             NamingQueryCmd, NamingRegisterCmd, NamingUtils,
         },
-        AuthInfo, ClientInfo, HostInfo, ServerEndpointInfo,
+        AuthInfo, ClientInfo, HostInfo, ServerEndpointInfo, TlsClient, TlsConfig,
+    },
+    grpc::{
+        ability::ServerAbilities, api_model::SUCCESS_CODE,
+        config_request_utils::GrpcConfigRequestUtils, grpc_client::InnerGrpcClient,
     },
-    grpc::grpc_client::InnerGrpcClient,
     init_global_system_actor, ActorCreate,
 };
 
 use super::{
-    breaker::BreakerConfig,
-    conn_msg::{
-        ConfigRequest, ConfigResponse, ConnCallbackMsg, NamingRequest, NamingResponse,
-        ServiceResult,
-    },
+    address_server::AddressServerConfig,
+    breaker::{Breaker, BreakerConfig},
+    conn_msg::{ConfigRequest, ConfigResponse, ConnCallbackMsg, NamingRequest, NamingResponse},
+    conn_state::{ConnState, ConnStateNotifier},
     inner_conn::InnerConn,
+    options::{ConnectionOptions, ProtocolMode},
+    retry::{is_retryable_error, ConnUnavailable},
     NotifyCallbackAddr,
 };
 
+//移除服务端地址时,等待处理中的请求完成后再关闭连接
+const REMOVED_CONN_CLOSE_GRACE: Duration = Duration::from_secs(3);
+
 #[derive(Default, Clone)]
 pub struct ConnManage {
     conns: Vec<InnerConn>,
@@ -43,6 +54,14 @@ pub struct ConnManage {
     pub(crate) callback: NotifyCallbackAddr,
     reconnecting: bool,
     client_info: Arc<ClientInfo>,
+    address_server: Option<AddressServerConfig>,
+    address_client: reqwest::Client,
+    tls: Option<TlsClient>,
+    conn_state: ConnStateNotifier,
+    reconnect_attempt: u32,
+    options: Arc<ConnectionOptions>,
+    auto_protocol: bool,
+    probing: bool,
 }
 
 impl ConnManage {
@@ -53,7 +72,6 @@ impl ConnManage {
         breaker_config: BreakerConfig,
         client_info: Arc<ClientInfo>,
     ) -> Self {
-        assert!(hosts.len() > 0);
         let mut id = 0;
         let breaker_config = Arc::new(breaker_config);
         let mut conns = Vec::with_capacity(hosts.len());
@@ -87,26 +105,117 @@ impl ConnManage {
         self
     }
 
+    /// 设置地址服务器后,启动时及之后定时从地址服务器更新服务端地址列表
+    pub fn set_address_server(mut self, address_server: Option<AddressServerConfig>) -> Self {
+        self.address_server = address_server;
+        self
+    }
+
+    /// 设置 TLS 配置,证书在设置时加载
+    pub fn set_tls_config(self, tls: Option<TlsConfig>) -> anyhow::Result<Self> {
+        let tls = match &tls {
+            Some(tls) => Some(TlsClient::new(tls)?),
+            None => None,
+        };
+        Ok(self.set_tls_client(tls))
+    }
+
+    /// 与 ServerEndpointInfo 共享已加载的 TLS 配置
+    pub(crate) fn set_tls_client(mut self, tls: Option<TlsClient>) -> Self {
+        //地址服务器使用 https 时同样需要 TLS 配置
+        if let Some(tls) = &tls {
+            self.address_client = tls.http_client();
+        }
+        self.tls = tls;
+        self
+    }
+
+    /// 设置协议模式,会覆盖 new 传入的 support_grpc;自动模式先使用 http 协议
+    pub fn set_protocol_mode(mut self, mode: ProtocolMode) -> Self {
+        self.auto_protocol = mode == ProtocolMode::Auto;
+        self.support_grpc = mode == ProtocolMode::Grpc;
+        for conn in &mut self.conns {
+            conn.support_grpc = self.support_grpc;
+        }
+        self
+    }
+
+    /// 设置连接配置,会覆盖 new 传入的熔断配置
+    pub fn set_connection_options(mut self, options: ConnectionOptions) -> Self {
+        self.breaker_config = Arc::new(options.breaker_config.clone());
+        for conn in &mut self.conns {
+            conn.breaker = Breaker::new(Default::default(), self.breaker_config.clone());
+        }
+        self.options = Arc::new(options);
+        self
+    }
+
+    fn current_conn_id(&self) -> Option<u32> {
+        self.conns.get(self.current_index).map(|e| e.id)
+    }
+
+    fn current_host(&self) -> Option<HostInfo> {
+        self.conns
+            .get(self.current_index)
+            .map(|e| e.host_info.clone())
+    }
+
+    /// 订阅连接状态变更
+    pub fn subscribe_state(&self) -> tokio::sync::watch::Receiver<ConnState> {
+        self.conn_state.subscribe()
+    }
+
+    /// 切换连接后通知状态;http 模式没有长连接,切换后即视为已连接
+    fn switched_notify(&mut self, from: Option<HostInfo>) {
+        if let (Some(from), Some(to)) = (from, self.current_host()) {
+            if from != to {
+                self.conn_state.notify(ConnState::Failover { from, to });
+            }
+        }
+        if !self.support_grpc {
+            self.connected_notify();
+        }
+    }
+
+    fn connected_notify(&mut self) {
+        if let Some(server) = self.current_host() {
+            self.conn_state.notify(ConnState::Connected { server });
+        }
+    }
+
     fn init_conn(&mut self, ctx: &mut Context<Self>) {
+        if self.conns.is_empty() {
+            log::warn!("ConnManage init connect,the server list is empty");
+            return;
+        }
         self.current_index = self.select_index();
+        self.connect_current(ctx);
+    }
+
+    fn connect_current(&mut self, ctx: &mut Context<Self>) {
         let conn = self.conns.get_mut(self.current_index).unwrap();
-        log::info!(
-            "ConnManage init connect,host {}:{}",
-            &conn.host_info.ip,
-            &conn.host_info.port
-        );
+        log::info!("ConnManage init connect,host {}", conn.host_info.get_addr());
         conn.breaker.clear();
         if self.support_grpc {
             let addr = ctx.address().downgrade();
-            conn.init_grpc(addr).ok();
+            if let Err(e) = conn.init_grpc(addr, &self.tls, &self.options) {
+                log::error!("ConnManage init grpc error,{}", e);
+            }
         } else {
-            Self::init_http_request(conn, &self.auth_info);
+            Self::init_http_request(conn, &self.auth_info, &self.tls, &self.options);
         }
     }
 
-    fn init_http_request(conn: &mut InnerConn, auth_info: &Option<AuthInfo>) {
+    fn init_http_request(
+        conn: &mut InnerConn,
+        auth_info: &Option<AuthInfo>,
+        tls: &Option<TlsClient>,
+        options: &Arc<ConnectionOptions>,
+    ) {
         let endpoints = Arc::new(ServerEndpointInfo {
             hosts: vec![conn.host_info.clone()],
+            tls: tls.clone(),
+            options: options.clone(),
         });
         let auth_actor = AuthActor::new(endpoints.clone(), auth_info.clone());
         let auth_actor_addr = auth_actor.start();
@@ -133,8 +242,15 @@ impl ConnManage {
     }
 
     fn select_index(&self) -> usize {
+        self.select_index_except(None)
+    }
+
+    /// 按熔断状态加权选择连接,except 为不参与选择的连接id
+    fn select_index_except(&self, except: Option<u32>) -> usize {
         NamingUtils::select_by_weight_fn(&self.conns, |e| {
-            if e.breaker.is_close() {
+            if except == Some(e.id) {
+                0
+            } else if e.breaker.is_close() {
                 1000
             } else if e.breaker.is_half_open() {
                 10
@@ -144,20 +260,36 @@ impl ConnManage {
         })
     }
 
-    fn reconnect(&mut self, old_index: usize, ctx: &mut Context<Self>) {
-        if self.reconnecting || self.current_index != old_index {
+    fn reconnect(&mut self, old_id: u32, ctx: &mut Context<Self>) {
+        if self.reconnecting || self.current_conn_id() != Some(old_id) {
             //log::debug!("ConnManage reconnect,ignore repeated");
             //已经重链过
             return;
         }
         self.reconnecting = true;
-        ctx.run_later(Duration::from_millis(1000), move |act, ctx| {
+        self.conn_state.notify(ConnState::Disconnected);
+        let delay = self
+            .options
+            .reconnect_backoff
+            .delay(self.reconnect_attempt + 1);
+        ctx.run_later(delay, move |act, ctx| {
+            act.reconnecting = false;
+            if act.current_conn_id() != Some(old_id) {
+                //等待期间服务端地址列表已更新并切换过连接
+                return;
+            }
             log::info!("ConnManage reconnect");
+            act.reconnect_attempt += 1;
+            act.conn_state.notify(ConnState::Reconnecting {
+                attempt: act.reconnect_attempt,
+            });
+            let old_index = act.current_index;
+            let old_host = act.current_host();
             if act.conns.len() == 1 {
                 act.init_conn(ctx);
             } else {
                 if let Some(conn) = act.conns.get_mut(old_index) {
-                    conn.close_grpc().ok();
+                    conn.close_grpc(Duration::ZERO).ok();
                     conn.weight = 0;
                 }
                 act.init_conn(ctx);
@@ -165,41 +297,242 @@ impl ConnManage {
                     conn.weight = 1;
                 }
             }
+            act.switched_notify(old_host);
             act.reconnect_notify(ctx);
-            act.reconnecting = false;
+            //grpc 不可用时回退到 http;http 协议下已有定时探测
+            if act.support_grpc {
+                act.probe_grpc(ctx);
+            }
         });
     }
 
-    fn reconnect_notify(&mut self, _ctx: &mut Context<Self>) {
-        if !self.support_grpc {
+    /// 切换连接,用于服务端要求重置连接(如服务端节点间重新平衡连接)及请求失败后重试;
+    /// 指定了目标地址且在地址列表中时切换到该地址,否则切换到其它地址,然后重新订阅
+    fn switch_conn(&mut self, old_id: u32, host: Option<HostInfo>, ctx: &mut Context<Self>) {
+        if self.current_conn_id() != Some(old_id) {
             return;
         }
-        if let Some(config_addr) = &self.callback.config_inner_addr {
-            if let Some(config_addr) = config_addr.upgrade() {
-                config_addr.do_send(ConfigInnerCmd::GrpcResubscribe);
+        self.conn_state.notify(ConnState::Disconnected);
+        let old_index = self.current_index;
+        let old_host = self.current_host();
+        let target_index = host.and_then(|host| {
+            let index = self.conns.iter().position(|e| e.host_info == host);
+            if index.is_none() {
+                log::warn!(
+                    "ConnManage switch conn,target server {} not in server list",
+                    host.get_addr()
+                );
             }
+            index
+        });
+        if let Some(conn) = self.conns.get_mut(old_index) {
+            conn.close_grpc(REMOVED_CONN_CLOSE_GRACE).ok();
+        }
+        self.current_index = match target_index {
+            Some(index) => index,
+            None if self.conns.len() > 1 => self.select_index_except(Some(old_id)),
+            None => old_index,
+        };
+        self.connect_current(ctx);
+        self.switched_notify(old_host);
+        self.reconnect_notify(ctx);
+    }
+
+    /// 更新服务端地址列表;新增的地址创建新连接,移除的地址关闭连接,
+    /// 当前连接的地址被移除时切换到其它地址并重新订阅
+    fn update_hosts(&mut self, hosts: Vec<HostInfo>, ctx: &mut Context<Self>) {
+        if hosts.is_empty() {
+            log::warn!("ConnManage ignore empty server list");
+            return;
+        }
+        if hosts.len() == self.conns.len()
+            && hosts
+                .iter()
+                .all(|host| self.conns.iter().any(|e| &e.host_info == host))
+        {
+            return;
         }
-        if let Some(naming_register_addr) = &self.callback.naming_register_addr {
-            if let Some(naming_register_addr) = naming_register_addr.upgrade() {
-                naming_register_addr.do_send(NamingRegisterCmd::Reregister);
+        let current_id = self.current_conn_id();
+        let current_host = self.current_host();
+        let mut old_conns = std::mem::take(&mut self.conns);
+        let mut conns = Vec::with_capacity(hosts.len());
+        for host in hosts {
+            if let Some(i) = old_conns.iter().position(|e| e.host_info == host) {
+                conns.push(old_conns.swap_remove(i));
+            } else {
+                log::info!("ConnManage add server,host {}", host.get_addr());
+                conns.push(InnerConn::new(
+                    self.conn_globda_id,
+                    host,
+                    self.support_grpc,
+                    self.breaker_config.clone(),
+                    self.client_info.clone(),
+                ));
+                self.conn_globda_id += 1;
             }
         }
-        if let Some(naming_listener_addr) = &self.callback.naming_listener_addr {
-            if let Some(naming_listener_addr) = naming_listener_addr.upgrade() {
-                naming_listener_addr.do_send(NamingListenerCmd::GrpcResubscribe);
+        for mut conn in old_conns {
+            log::info!(
+                "ConnManage remove server,host {}",
+                conn.host_info.get_addr()
+            );
+            conn.close_grpc(REMOVED_CONN_CLOSE_GRACE).ok();
+        }
+        self.conn_map = conns
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.id, i as u32))
+            .collect();
+        self.conns = conns;
+        self.update_hosts_notify();
+        match current_id.and_then(|id| self.conn_map.get(&id)) {
+            Some(index) => self.current_index = *index as usize,
+            None => {
+                self.conn_state.notify(ConnState::Disconnected);
+                self.init_conn(ctx);
+                self.switched_notify(current_host);
+                self.reconnect_notify(ctx);
             }
         }
     }
 
-    fn check_reconnect(
-        &mut self,
-        current_index: usize,
-        request_is_ok: bool,
-        ctx: &mut Context<Self>,
-    ) {
-        let can_try = if let Some(conn) = self.conns.get_mut(current_index) {
+    /// 自动协议模式下探测当前服务端的 grpc 端口是否可用,并切换协议;
+    /// 使用 http 协议时按 protocol_probe_interval 定时探测,以便服务端升级后切换到 grpc
+    fn probe_grpc(&mut self, ctx: &mut Context<Self>) {
+        if !self.auto_protocol || self.probing {
+            return;
+        }
+        let conn = match self.conns.get(self.current_index) {
+            Some(conn) => conn,
+            None => return,
+        };
+        let conn_id = conn.id;
+        let channel = match conn.build_grpc_channel(&self.tls) {
+            Ok(channel) => channel,
+            Err(e) => {
+                log::error!("ConnManage probe grpc error,{}", e);
+                return;
+            }
+        };
+        let timeout = self.options.request_timeout;
+        self.probing = true;
+        async move { GrpcConfigRequestUtils::server_check(channel, timeout).await }
+            .into_actor(self)
+            .map(move |res, act, ctx| {
+                act.probing = false;
+                let use_grpc = match res {
+                    Ok(res) => res.result_code == SUCCESS_CODE,
+                    Err(e) => {
+                        log::info!("ConnManage probe grpc failed,{}", e);
+                        false
+                    }
+                };
+                if act.current_conn_id() == Some(conn_id) {
+                    act.set_protocol(use_grpc, ctx);
+                }
+                if !act.support_grpc {
+                    ctx.run_later(act.options.protocol_probe_interval, |act, ctx| {
+                        act.probe_grpc(ctx)
+                    });
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn set_protocol(&mut self, use_grpc: bool, ctx: &mut Context<Self>) {
+        if self.support_grpc == use_grpc {
+            return;
+        }
+        log::info!(
+            "ConnManage switch protocol to {}",
+            if use_grpc { "grpc" } else { "http" }
+        );
+        if let Some(conn) = self.conns.get_mut(self.current_index) {
+            conn.close_grpc(REMOVED_CONN_CLOSE_GRACE).ok();
+        }
+        self.support_grpc = use_grpc;
+        for conn in &mut self.conns {
+            conn.support_grpc = use_grpc;
+        }
+        self.conn_state.notify(ConnState::Disconnected);
+        if !self.conns.is_empty() {
+            self.connect_current(ctx);
+            self.switched_notify(None);
+        }
+        for addr in self.callback.config_inner_addrs() {
+            addr.do_send(ConfigInnerCmd::UpdateProtocol(use_grpc));
+        }
+        for addr in self.callback.naming_register_addrs() {
+            addr.do_send(NamingRegisterCmd::UpdateProtocol(use_grpc));
+        }
+        for addr in self.callback.naming_listener_addrs() {
+            addr.do_send(NamingListenerCmd::UpdateProtocol(use_grpc));
+        }
+    }
+
+    fn server_hosts(&self) -> Vec<HostInfo> {
+        self.conns.iter().map(|e| e.host_info.clone()).collect()
+    }
+
+    fn update_hosts_notify(&self) {
+        let hosts = self.server_hosts();
+        for naming_listener_addr in self.callback.naming_listener_addrs() {
+            naming_listener_addr.do_send(NamingListenerCmd::UpdateServerHosts(hosts.clone()));
+        }
+    }
+
+    fn refresh_server_list(&mut self, ctx: &mut Context<Self>) {
+        let config = match &self.address_server {
+            Some(v) => v.clone(),
+            None => return,
+        };
+        let client = self.address_client.clone();
+        let timeout = self.options.request_timeout_millis();
+        async move { config.fetch_hosts(&client, timeout).await }
+            .into_actor(self)
+            .map(|res, act, ctx| {
+                match res {
+                    Ok(hosts) => act.update_hosts(hosts, ctx),
+                    Err(e) => log::warn!("ConnManage refresh server list error,{}", e),
+                }
+                //还没有可用的地址时尽快重试
+                let interval = match &act.address_server {
+                    Some(config) if !act.conns.is_empty() => config.refresh_interval,
+                    _ => Duration::from_secs(3),
+                };
+                ctx.run_later(interval, |act, ctx| act.refresh_server_list(ctx));
+            })
+            .spawn(ctx);
+    }
+
+    fn reconnect_notify(&mut self, _ctx: &mut Context<Self>) {
+        if !self.support_grpc {
+            return;
+        }
+        for config_addr in self.callback.config_inner_addrs() {
+            config_addr.do_send(ConfigInnerCmd::GrpcResubscribe);
+        }
+        for naming_register_addr in self.callback.naming_register_addrs() {
+            naming_register_addr.do_send(NamingRegisterCmd::Reregister);
+        }
+        for naming_listener_addr in self.callback.naming_listener_addrs() {
+            naming_listener_addr.do_send(NamingListenerCmd::GrpcResubscribe);
+        }
+    }
+
+    fn check_reconnect(&mut self, conn_id: u32, request_is_ok: bool, ctx: &mut Context<Self>) {
+        let index = match self.conn_map.get(&conn_id) {
+            Some(index) => *index as usize,
+            //连接已被移除
+            None => return,
+        };
+        let can_try = if let Some(conn) = self.conns.get_mut(index) {
             if request_is_ok {
                 conn.breaker.success();
+                //新连接上的请求成功后才重置重连退避
+                if index == self.current_index {
+                    self.reconnect_attempt = 0;
+                }
                 true
             } else {
                 conn.breaker.error();
@@ -209,8 +542,176 @@ impl ConnManage {
             true
         };
         if !can_try {
-            self.reconnect(current_index, ctx);
+            self.reconnect(conn_id, ctx);
+        }
+    }
+
+    /// 请求失败后判断是否重试,需要重试时返回等待时间;等待后的请求在截止时间前超时则不再重试
+    fn retry_delay(&self, deadline: Instant, attempt: u32) -> Option<Duration> {
+        let delay = self.options.retry.backoff.delay(attempt + 1);
+        if Instant::now() + delay + self.options.request_timeout > deadline {
+            return None;
+        }
+        Some(delay)
+    }
+
+    /// 选择处理请求的连接;重试时无状态的请求发送到失败连接以外的服务端,
+    /// 与连接绑定的请求(监听、订阅、注册)仍使用当前连接,当前连接的切换由 check_reconnect 与熔断处理
+    fn request_index(&self, failed_conn_id: Option<u32>, conn_bound: bool) -> usize {
+        match failed_conn_id {
+            Some(id) if !conn_bound && self.conns.len() > 1 => self.select_index_except(Some(id)),
+            _ => self.current_index,
+        }
+    }
+
+    /// 非当前连接没有 grpc 连接时使用 http 请求
+    fn request_use_grpc(&mut self, index: usize) -> bool {
+        let current_index = self.current_index;
+        let support_grpc = self.support_grpc;
+        match self.conns.get_mut(index) {
+            Some(conn) => {
+                let use_grpc =
+                    support_grpc && (index == current_index || conn.grpc_client_addr.is_some());
+                if !use_grpc && conn.naming_request_client.is_none() {
+                    Self::init_http_request(conn, &self.auth_info, &self.tls, &self.options);
+                }
+                use_grpc
+            }
+            None => support_grpc,
+        }
+    }
+
+    fn config_request(
+        &mut self,
+        msg: ConfigRequest,
+        deadline: Instant,
+        attempt: u32,
+        failed_conn_id: Option<u32>,
+    ) -> ResponseActFuture<Self, anyhow::Result<ConfigResponse>> {
+        let retry_msg = if msg.can_retry(self.options.retry.retry_non_idempotent) {
+            Some(msg.clone())
+        } else {
+            None
+        };
+        let index = self.request_index(failed_conn_id, self.support_grpc && msg.is_conn_bound());
+        let support_grpc = self.request_use_grpc(index);
+        let conn = self.conns.get(index).map(|conn| {
+            (
+                conn.id,
+                conn.grpc_client_addr.clone(),
+                conn.config_request_client.clone(),
+            )
+        });
+        let fut = async move {
+            match conn {
+                Some((conn_id, conn_addr, config_client)) => {
+                    let r =
+                        Self::do_config_request(msg, support_grpc, conn_addr, config_client).await;
+                    (r, Some(conn_id))
+                }
+                None => (Err(ConnUnavailable("nacos server is empty").into()), None),
+            }
         }
+        .into_actor(self)
+        .then(move |(r, conn_id), act, ctx| {
+            if let Some(conn_id) = conn_id {
+                act.check_reconnect(conn_id, r.is_ok(), ctx);
+            }
+            let res: ResponseActFuture<Self, anyhow::Result<ConfigResponse>> = match (r, retry_msg)
+            {
+                (Err(err), Some(msg)) if is_retryable_error(&err) => {
+                    match act.retry_delay(deadline, attempt) {
+                        Some(delay) => {
+                            log::warn!(
+                                "ConnManage config request error,retry after {:?},{}",
+                                delay,
+                                err
+                            );
+                            Box::pin(fut::wrap_future(tokio::time::sleep(delay)).then(
+                                move |_, act: &mut Self, _| {
+                                    act.config_request(msg, deadline, attempt + 1, conn_id)
+                                },
+                            ))
+                        }
+                        None => Box::pin(fut::ready(Err(err))),
+                    }
+                }
+                (r, _) => Box::pin(fut::ready(r)),
+            };
+            res
+        });
+        Box::pin(fut)
+    }
+
+    fn naming_request(
+        &mut self,
+        msg: NamingRequest,
+        deadline: Instant,
+        attempt: u32,
+        failed_conn_id: Option<u32>,
+    ) -> ResponseActFuture<Self, anyhow::Result<NamingResponse>> {
+        let retry_msg = if msg.can_retry() {
+            Some(msg.clone())
+        } else {
+            None
+        };
+        let index = self.request_index(failed_conn_id, self.support_grpc && msg.is_conn_bound());
+        let use_grpc = self.request_use_grpc(index);
+        let conn = match self.conns.get_mut(index) {
+            Some(conn) => {
+                let support_grpc = use_grpc && conn.grpc_support_naming_request(&msg);
+                if !support_grpc && conn.naming_request_client.is_none() {
+                    //服务端不支持的请求回退为 http 请求
+                    Self::init_http_request(conn, &self.auth_info, &self.tls, &self.options);
+                }
+                Some((
+                    conn.id,
+                    support_grpc,
+                    conn.grpc_client_addr.clone(),
+                    conn.naming_request_client.clone(),
+                ))
+            }
+            None => None,
+        };
+        let fut = async move {
+            match conn {
+                Some((conn_id, support_grpc, conn_addr, naming_client)) => {
+                    let r =
+                        Self::do_naming_request(msg, support_grpc, conn_addr, naming_client).await;
+                    (r, Some(conn_id))
+                }
+                None => (Err(ConnUnavailable("nacos server is empty").into()), None),
+            }
+        }
+        .into_actor(self)
+        .then(move |(r, conn_id), act, ctx| {
+            if let Some(conn_id) = conn_id {
+                act.check_reconnect(conn_id, r.is_ok(), ctx);
+            }
+            let res: ResponseActFuture<Self, anyhow::Result<NamingResponse>> = match (r, retry_msg)
+            {
+                (Err(err), Some(msg)) if is_retryable_error(&err) => {
+                    match act.retry_delay(deadline, attempt) {
+                        Some(delay) => {
+                            log::warn!(
+                                "ConnManage naming request error,retry after {:?},{}",
+                                delay,
+                                err
+                            );
+                            Box::pin(fut::wrap_future(tokio::time::sleep(delay)).then(
+                                move |_, act: &mut Self, _| {
+                                    act.naming_request(msg, deadline, attempt + 1, conn_id)
+                                },
+                            ))
+                        }
+                        None => Box::pin(fut::ready(Err(err))),
+                    }
+                }
+                (r, _) => Box::pin(fut::ready(r)),
+            };
+            res
+        });
+        Box::pin(fut)
     }
 
     async fn do_config_request(
@@ -221,13 +722,10 @@ impl ConnManage {
     ) -> anyhow::Result<ConfigResponse> {
         if support_grpc {
             if let Some(conn_addr) = conn_addr {
-                if let Ok(Ok(res)) = conn_addr.send(msg).await {
-                    Ok(res)
-                } else {
-                    Err(anyhow::anyhow!("grpc request error"))
-                }
+                let res: ConfigResponse = conn_addr.send(msg).await??;
+                Ok(res)
             } else {
-                Err(anyhow::anyhow!("grpc conn is empty"))
+                Err(ConnUnavailable("grpc conn is empty").into())
             }
         } else {
             if let Some(config_client) = config_client {
@@ -252,7 +750,7 @@ impl ConnManage {
                     ConfigRequest::Listen(_, _) => Err(anyhow::anyhow!("http not support")),
                 }
             } else {
-                Err(anyhow::anyhow!("config client is empty"))
+                Err(ConnUnavailable("config client is empty").into())
             }
         }
     }
@@ -268,43 +766,33 @@ impl ConnManage {
                 let res: NamingResponse = conn_addr.send(msg).await??;
                 Ok(res)
             } else {
-                Err(anyhow::anyhow!("grpc conn is empty"))
+                Err(ConnUnavailable("grpc conn is empty").into())
             }
         } else {
             if let Some(naming_client) = naming_client {
                 match msg {
                     NamingRequest::Register(instance) => {
-                        naming_client.register(&instance).await?;
-                        Ok(NamingResponse::None)
+                        let res = naming_client.register(&instance).await?;
+                        Ok(NamingResponse::V1OperateResult(res))
                     }
                     NamingRequest::Unregister(instance) => {
-                        naming_client.remove(&instance).await?;
-                        Ok(NamingResponse::None)
+                        let res = naming_client.remove(&instance).await?;
+                        Ok(NamingResponse::V1OperateResult(res))
                     }
                     NamingRequest::BatchRegister(_) => Err(anyhow::anyhow!("http not support")),
                     NamingRequest::Subscribe(_) => Err(anyhow::anyhow!("http not support")),
                     NamingRequest::Unsubscribe(_) => Err(anyhow::anyhow!("http not support")),
                     NamingRequest::QueryInstance(param) => {
                         let result = naming_client.get_instance_list(&param).await?;
-                        let hosts = result
-                            .hosts
-                            .unwrap_or_default()
-                            .into_iter()
-                            .map(|e| Arc::new(e.to_instance()))
-                            .collect();
-                        let service_result = ServiceResult {
-                            hosts,
-                            cache_millis: result.cache_millis,
-                        };
-                        Ok(NamingResponse::ServiceResult(service_result))
+                        Ok(NamingResponse::ServiceResult(result.to_service_result()))
                     }
                     NamingRequest::V1Heartbeat(heartbeat) => {
-                        naming_client.heartbeat(heartbeat).await?;
-                        Ok(NamingResponse::None)
+                        let res = naming_client.heartbeat(heartbeat).await?;
+                        Ok(NamingResponse::V1BeatResult(res))
                     }
                 }
             } else {
-                Err(anyhow::anyhow!("naming client is empty"))
+                Err(ConnUnavailable("naming client is empty").into())
             }
         }
     }
@@ -322,6 +810,9 @@ impl Actor for ConnManage {
     fn started(&mut self, ctx: &mut Self::Context) {
         log::info!("ConnManage started");
         self.init_conn(ctx);
+        self.switched_notify(None);
+        self.probe_grpc(ctx);
+        self.refresh_server_list(ctx);
     }
 }
 
@@ -331,7 +822,25 @@ pub enum ConnManageCmd {
     ConfigInnerActorAddr(WeakAddr<ConfigInnerActor>),
     NamingListenerActorAddr(WeakAddr<InnerNamingListener>),
     NamingRegisterActorAddr(WeakAddr<InnerNamingRegister>),
-    GrpcRequestCheckError { id: u32 },
+    GrpcRequestCheckError {
+        id: u32,
+    },
+    /// 运行时更新服务端地址列表
+    UpdateHosts(Vec<HostInfo>),
+    /// 连接与服务端协商得到的服务端能力
+    ServerAbilities {
+        id: u32,
+        abilities: Arc<ServerAbilities>,
+    },
+    /// grpc 连接建立并通过服务端校验
+    GrpcConnected {
+        id: u32,
+    },
+    /// 服务端要求重置连接,host 为服务端指定的目标地址
+    ConnectReset {
+        id: u32,
+        host: Option<HostInfo>,
+    },
 }
 
 impl Handler<ConnManageCmd> for ConnManage {
@@ -339,16 +848,55 @@ impl Handler<ConnManageCmd> for ConnManage {
 
     fn handle(&mut self, msg: ConnManageCmd, ctx: &mut Self::Context) -> Self::Result {
         match msg {
+            //自动协议模式下,注册时协议可能已经切换
             ConnManageCmd::ConfigInnerActorAddr(addr) => {
-                self.callback.config_inner_addr = Some(addr);
+                if let (true, Some(addr)) = (self.auto_protocol, addr.upgrade()) {
+                    addr.do_send(ConfigInnerCmd::UpdateProtocol(self.support_grpc));
+                }
+                self.callback.remove_stopped();
+                self.callback.config_inner_addrs.push(addr);
             }
             ConnManageCmd::NamingListenerActorAddr(addr) => {
-                self.callback.naming_listener_addr = Some(addr);
+                if let Some(listener_addr) = addr.upgrade() {
+                    if self.auto_protocol {
+                        listener_addr.do_send(NamingListenerCmd::UpdateProtocol(self.support_grpc));
+                    }
+                    //共享连接时后创建的监听器使用当前的服务端地址列表
+                    if !self.conns.is_empty() {
+                        listener_addr
+                            .do_send(NamingListenerCmd::UpdateServerHosts(self.server_hosts()));
+                    }
+                }
+                self.callback.remove_stopped();
+                self.callback.naming_listener_addrs.push(addr);
             }
             ConnManageCmd::NamingRegisterActorAddr(addr) => {
-                self.callback.naming_register_addr = Some(addr);
+                if let (true, Some(addr)) = (self.auto_protocol, addr.upgrade()) {
+                    addr.do_send(NamingRegisterCmd::UpdateProtocol(self.support_grpc));
+                }
+                self.callback.remove_stopped();
+                self.callback.naming_register_addrs.push(addr);
+            }
+            ConnManageCmd::GrpcRequestCheckError { id } => self.reconnect(id, ctx),
+            ConnManageCmd::UpdateHosts(hosts) => self.update_hosts(hosts, ctx),
+            ConnManageCmd::ConnectReset { id, host } => self.switch_conn(id, host, ctx),
+            ConnManageCmd::GrpcConnected { id } => {
+                if self.current_conn_id() == Some(id) {
+                    self.connected_notify();
+                }
+            }
+            ConnManageCmd::ServerAbilities { id, abilities } => {
+                if let Some(index) = self.conn_map.get(&id) {
+                    if let Some(conn) = self.conns.get_mut(*index as usize) {
+                        log::info!(
+                            "ConnManage server abilities,host {},{:?}",
+                            conn.host_info.get_addr(),
+                            &abilities
+                        );
+                        conn.server_abilities = abilities;
+                    }
+                }
             }
-            ConnManageCmd::GrpcRequestCheckError { id } => self.reconnect(id as usize, ctx),
         }
         Ok(())
     }
@@ -360,22 +908,22 @@ impl Handler<ConnCallbackMsg> for ConnManage {
         let callback = self.callback.clone();
         let fut = async move {
             match msg {
+                //各 actor 只处理自己命名空间下的变更
                 ConnCallbackMsg::ConfigChange(config_key, content, md5) => {
-                    if let Some(config_addr) = callback.config_inner_addr {
-                        if let Some(config_addr) = config_addr.upgrade() {
-                            config_addr.do_send(ConfigInnerCmd::Notify(vec![NotifyConfigItem {
-                                key: config_key,
-                                content,
-                                md5,
-                            }]));
-                        }
+                    for config_addr in callback.config_inner_addrs() {
+                        config_addr.do_send(ConfigInnerCmd::Notify(vec![NotifyConfigItem {
+                            key: config_key.clone(),
+                            content: content.clone(),
+                            md5: md5.clone(),
+                        }]));
                     }
                 }
                 ConnCallbackMsg::InstanceChange(key, service_result) => {
-                    if let Some(config_addr) = callback.naming_listener_addr {
-                        if let Some(config_addr) = config_addr.upgrade() {
-                            config_addr.do_send(NamingQueryCmd::ChangeResult(key, service_result));
-                        }
+                    for naming_addr in callback.naming_listener_addrs() {
+                        naming_addr.do_send(NamingQueryCmd::ChangeResult(
+                            key.clone(),
+                            service_result.clone(),
+                        ));
                     }
                 }
             }
@@ -392,42 +940,16 @@ impl Handler<ConfigRequest> for ConnManage {
     type Result = ResponseActFuture<Self, anyhow::Result<ConfigResponse>>;
 
     fn handle(&mut self, msg: ConfigRequest, _ctx: &mut Self::Context) -> Self::Result {
-        let conn = self.conns.get(self.current_index).unwrap();
-        let conn_addr = conn.grpc_client_addr.clone();
-        let current_index = self.current_index.to_owned();
-        let support_grpc = self.support_grpc;
-        let config_client = conn.config_request_client.clone();
-        let fut = async move {
-            let r = Self::do_config_request(msg, support_grpc, conn_addr, config_client).await;
-            (r, current_index)
-        }
-        .into_actor(self)
-        .map(|(r, current_index), act, ctx| {
-            act.check_reconnect(current_index, r.is_ok(), ctx);
-            r
-        });
-        Box::pin(fut)
+        let deadline = Instant::now() + self.options.retry.deadline;
+        self.config_request(msg, deadline, 0, None)
     }
 }
 
 impl Handler<NamingRequest> for ConnManage {
     type Result = ResponseActFuture<Self, anyhow::Result<NamingResponse>>;
 
-    fn handle(&mut self, msg: NamingRequest, ctx: &mut Self::Context) -> Self::Result {
-        let conn = self.conns.get(self.current_index).unwrap();
-        let conn_addr = conn.grpc_client_addr.clone();
-        let support_grpc = self.support_grpc;
-        let naming_client = conn.naming_request_client.clone();
-        let current_index = self.current_index.to_owned();
-        let fut = async move {
-            let r = Self::do_naming_request(msg, support_grpc, conn_addr, naming_client).await;
-            (r, current_index)
-        }
-        .into_actor(self)
-        .map(|(r, current_index), act, ctx| {
-            act.check_reconnect(current_index, r.is_ok(), ctx);
-            r
-        });
-        Box::pin(fut)
+    fn handle(&mut self, msg: NamingRequest, _ctx: &mut Self::Context) -> Self::Result {
+        let deadline = Instant::now() + self.options.retry.deadline;
+        self.naming_request(msg, deadline, 0, None)
     }
 }
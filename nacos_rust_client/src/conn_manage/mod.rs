@@ -1,19 +1,52 @@
-use actix::WeakAddr;
+use actix::{Actor, Addr, WeakAddr};
 
 use crate::client::{
     config_client::inner::ConfigInnerActor,
     naming_client::{InnerNamingListener, InnerNamingRegister},
 };
 
-pub(crate) mod breaker;
+pub mod address_server;
+pub mod breaker;
 pub mod conn_msg;
+pub mod conn_state;
 pub mod endpoint;
 pub(crate) mod inner_conn;
 pub mod manage;
+pub mod options;
+pub(crate) mod retry;
 
+pub use self::conn_state::ConnState;
+pub use self::options::{Backoff, ConnectionOptions, ProtocolMode, RetryOptions};
+
+/// 连接事件的回调 actor;多个命名空间共享连接时,每个命名空间各有一组 actor
 #[derive(Default, Clone)]
 pub struct NotifyCallbackAddr {
-    pub(crate) config_inner_addr: Option<WeakAddr<ConfigInnerActor>>,
-    pub(crate) naming_listener_addr: Option<WeakAddr<InnerNamingListener>>,
-    pub(crate) naming_register_addr: Option<WeakAddr<InnerNamingRegister>>,
+    pub(crate) config_inner_addrs: Vec<WeakAddr<ConfigInnerActor>>,
+    pub(crate) naming_listener_addrs: Vec<WeakAddr<InnerNamingListener>>,
+    pub(crate) naming_register_addrs: Vec<WeakAddr<InnerNamingRegister>>,
+}
+
+impl NotifyCallbackAddr {
+    pub(crate) fn config_inner_addrs(&self) -> Vec<Addr<ConfigInnerActor>> {
+        upgrade_all(&self.config_inner_addrs)
+    }
+
+    pub(crate) fn naming_listener_addrs(&self) -> Vec<Addr<InnerNamingListener>> {
+        upgrade_all(&self.naming_listener_addrs)
+    }
+
+    pub(crate) fn naming_register_addrs(&self) -> Vec<Addr<InnerNamingRegister>> {
+        upgrade_all(&self.naming_register_addrs)
+    }
+
+    /// 移除已停止的 actor
+    pub(crate) fn remove_stopped(&mut self) {
+        self.config_inner_addrs.retain(|e| e.upgrade().is_some());
+        self.naming_listener_addrs.retain(|e| e.upgrade().is_some());
+        self.naming_register_addrs.retain(|e| e.upgrade().is_some());
+    }
+}
+
+fn upgrade_all<A: Actor>(addrs: &[WeakAddr<A>]) -> Vec<Addr<A>> {
+    addrs.iter().filter_map(|e| e.upgrade()).collect()
 }
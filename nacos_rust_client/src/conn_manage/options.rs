@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use super::breaker::BreakerConfig;
+
+/// 与服务端通信的协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolMode {
+    /// nacos 2.x grpc 协议
+    #[default]
+    Grpc,
+    /// nacos 1.x http 协议
+    Http,
+    /// 先使用 http 协议,探测到服务端 grpc 端口可用后切换到 grpc;grpc 不可用时再回退到 http
+    Auto,
+}
+
+/// 重连与请求重试的退避策略
+///
+/// 第 n 次重试等待 `initial * 2^(n-1)`,不超过 max;再按 jitter 比例随机浮动,避免大量客户端同时重连。
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// 随机浮动比例,取值 [0,1]
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(1000),
+            max: Duration::from_secs(30),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// 第 attempt 次(从1开始)重试前的等待时间
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let shift = attempt.saturating_sub(1).min(16);
+        let delay = self.initial.saturating_mul(1 << shift).min(self.max);
+        let jitter = self.jitter.clamp(0f64, 1f64);
+        if jitter == 0f64 {
+            return delay;
+        }
+        let rate = rand::thread_rng().gen_range(1f64 - jitter..=1f64 + jitter);
+        delay.mul_f64(rate)
+    }
+}
+
+/// 请求失败后的重试配置
+///
+/// 只重试连接类错误(超时、连接断开等);有多个服务端地址时先切换到其它地址再重试。
+#[derive(Debug, Clone)]
+pub struct RetryOptions {
+    /// 从首次请求开始计算的重试截止时间,超过后不再发起重试;为0时不重试
+    pub deadline: Duration,
+    pub backoff: Backoff,
+    /// 是否重试非幂等请求(发布、删除配置)
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            deadline: Duration::from_secs(5),
+            backoff: Backoff {
+                initial: Duration::from_millis(200),
+                max: Duration::from_secs(2),
+                jitter: 0.2,
+            },
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+/// 与服务端连接相关的配置
+#[derive(Debug, Clone)]
+pub struct ConnectionOptions {
+    /// 连接熔断配置
+    pub breaker_config: BreakerConfig,
+    pub reconnect_backoff: Backoff,
+    pub retry: RetryOptions,
+    /// grpc 与 http 单次请求超时
+    pub request_timeout: Duration,
+    /// http 模式配置长轮询超时
+    pub long_poll_timeout: Duration,
+    /// grpc 连接心跳检测间隔
+    pub keepalive_interval: Duration,
+    /// 自动协议模式下,使用 http 协议时探测 grpc 是否可用的间隔
+    pub protocol_probe_interval: Duration,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            breaker_config: Default::default(),
+            reconnect_backoff: Default::default(),
+            retry: Default::default(),
+            request_timeout: Duration::from_millis(3000),
+            long_poll_timeout: Duration::from_millis(30000),
+            keepalive_interval: Duration::from_millis(5000),
+            protocol_probe_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ConnectionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_breaker_config(mut self, breaker_config: BreakerConfig) -> Self {
+        self.breaker_config = breaker_config;
+        self
+    }
+
+    pub fn set_reconnect_backoff(mut self, reconnect_backoff: Backoff) -> Self {
+        self.reconnect_backoff = reconnect_backoff;
+        self
+    }
+
+    pub fn set_retry(mut self, retry: RetryOptions) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn set_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    pub fn set_long_poll_timeout(mut self, long_poll_timeout: Duration) -> Self {
+        self.long_poll_timeout = long_poll_timeout;
+        self
+    }
+
+    pub fn set_keepalive_interval(mut self, keepalive_interval: Duration) -> Self {
+        self.keepalive_interval = keepalive_interval;
+        self
+    }
+
+    pub fn set_protocol_probe_interval(mut self, protocol_probe_interval: Duration) -> Self {
+        self.protocol_probe_interval = protocol_probe_interval;
+        self
+    }
+
+    pub(crate) fn request_timeout_millis(&self) -> u64 {
+        self.request_timeout.as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Backoff;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(5),
+            jitter: 0f64,
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(500));
+        assert_eq!(backoff.delay(3), Duration::from_millis(2000));
+        assert_eq!(backoff.delay(10), Duration::from_secs(5));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(5));
+
+        let backoff = Backoff {
+            jitter: 0.5,
+            ..backoff
+        };
+        for _ in 0..10 {
+            let delay = backoff.delay(2);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1500));
+        }
+    }
+}
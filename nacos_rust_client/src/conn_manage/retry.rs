@@ -0,0 +1,108 @@
+use std::fmt;
+
+use super::conn_msg::{ConfigRequest, NamingRequest};
+
+/// 没有可用连接
+#[derive(Debug)]
+pub(crate) struct ConnUnavailable(pub &'static str);
+
+impl fmt::Display for ConnUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for ConnUnavailable {}
+
+/// 是否为连接类错误;服务端正常返回的业务错误不重试
+pub(crate) fn is_retryable_error(err: &anyhow::Error) -> bool {
+    if err.downcast_ref::<ConnUnavailable>().is_some()
+        || err.downcast_ref::<tokio::time::error::Elapsed>().is_some()
+        || err.downcast_ref::<actix::MailboxError>().is_some()
+    {
+        return true;
+    }
+    if let Some(status) = err.downcast_ref::<tonic::Status>() {
+        return matches!(
+            status.code(),
+            tonic::Code::Unavailable
+                | tonic::Code::DeadlineExceeded
+                | tonic::Code::Cancelled
+                | tonic::Code::Aborted
+                | tonic::Code::Unknown
+        );
+    }
+    if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+        return e.is_connect() || e.is_timeout() || e.is_request();
+    }
+    false
+}
+
+impl ConfigRequest {
+    pub(crate) fn can_retry(&self, retry_non_idempotent: bool) -> bool {
+        match self {
+            ConfigRequest::GetConfig(_) | ConfigRequest::Listen(_, _) => true,
+            ConfigRequest::SetConfig(_, _) | ConfigRequest::DeleteConfig(_) => retry_non_idempotent,
+            //长轮询由监听循环重新发起
+            ConfigRequest::V1Listen(_) => false,
+        }
+    }
+
+    /// grpc 协议下监听与连接绑定,服务端通过该连接推送变更
+    pub(crate) fn is_conn_bound(&self) -> bool {
+        matches!(self, ConfigRequest::Listen(_, _))
+    }
+}
+
+impl NamingRequest {
+    /// 注册、注销、订阅都以实例或服务为key设置最终状态,重复请求结果相同,都是幂等的,
+    /// 因此不受 retry_non_idempotent 控制
+    pub(crate) fn can_retry(&self) -> bool {
+        match self {
+            //心跳会周期发送
+            NamingRequest::V1Heartbeat(_) => false,
+            _ => true,
+        }
+    }
+
+    /// grpc 协议下注册的临时实例与订阅都与连接绑定
+    pub(crate) fn is_conn_bound(&self) -> bool {
+        !matches!(
+            self,
+            NamingRequest::QueryInstance(_) | NamingRequest::V1Heartbeat(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{config_client::ConfigKey, naming_client::Instance};
+
+    use super::{is_retryable_error, ConfigRequest, ConnUnavailable, NamingRequest};
+
+    #[test]
+    fn test_retryable() {
+        assert!(is_retryable_error(&anyhow::Error::new(ConnUnavailable(
+            "nacos server is empty"
+        ))));
+        assert!(is_retryable_error(&anyhow::Error::new(
+            tonic::Status::unavailable("conn refused")
+        )));
+        assert!(!is_retryable_error(&anyhow::Error::new(
+            tonic::Status::permission_denied("no auth")
+        )));
+        assert!(!is_retryable_error(&anyhow::anyhow!("response error code")));
+
+        let key = ConfigKey::new("data_id", "group", "");
+        assert!(ConfigRequest::GetConfig(key.clone()).can_retry(false));
+        assert!(!ConfigRequest::SetConfig(key.clone(), "v".to_owned()).can_retry(false));
+        assert!(ConfigRequest::DeleteConfig(key.clone()).can_retry(true));
+        assert!(!ConfigRequest::V1Listen("".to_owned()).can_retry(true));
+        assert!(!ConfigRequest::GetConfig(key.clone()).is_conn_bound());
+        assert!(ConfigRequest::Listen(vec![(key, "".to_owned())], true).is_conn_bound());
+
+        let instance = Instance::new_simple("127.0.0.1", 8080, "foo", "");
+        assert!(NamingRequest::Unregister(instance).can_retry());
+        assert!(!NamingRequest::V1Heartbeat(Default::default()).can_retry());
+    }
+}
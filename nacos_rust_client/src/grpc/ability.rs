@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+/// 支持通过 grpc 注册持久化实例(nacos 2.3+)
+pub const SUPPORT_PERSISTENT_INSTANCE_BY_GRPC: &str = "supportPersistentInstanceByGrpc";
+
+/// 服务端能力,由 ServerCheckResponse 与 SetupAckRequest 协商得到
+#[derive(Debug, Clone, Default)]
+pub struct ServerAbilities {
+    /// 服务端是否支持能力协商,nacos 2.3 之前的版本不支持
+    pub support_negotiation: bool,
+    pub ability_table: HashMap<String, bool>,
+}
+
+impl ServerAbilities {
+    /// 服务端没有声明的能力按不支持处理
+    pub fn is_supported(&self, key: &str) -> bool {
+        self.ability_table.get(key).copied().unwrap_or(false)
+    }
+
+    pub fn support_persistent_instance_by_grpc(&self) -> bool {
+        self.is_supported(SUPPORT_PERSISTENT_INSTANCE_BY_GRPC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{ServerAbilities, SUPPORT_PERSISTENT_INSTANCE_BY_GRPC};
+
+    #[test]
+    fn test_server_abilities() {
+        let abilities = ServerAbilities::default();
+        assert!(!abilities.support_persistent_instance_by_grpc());
+        let mut ability_table = HashMap::new();
+        ability_table.insert(SUPPORT_PERSISTENT_INSTANCE_BY_GRPC.to_owned(), true);
+        ability_table.insert("supportRemoteConnection".to_owned(), false);
+        let abilities = ServerAbilities {
+            support_negotiation: true,
+            ability_table,
+        };
+        assert!(abilities.support_persistent_instance_by_grpc());
+        assert!(!abilities.is_supported("supportRemoteConnection"));
+    }
+}
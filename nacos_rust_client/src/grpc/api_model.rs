@@ -2,8 +2,12 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use crate::client::HostInfo;
+
 pub const SUCCESS_CODE: u16 = 200u16;
 pub const ERROR_CODE: u16 = 500u16;
+//服务端推送的请求没有对应的处理逻辑
+pub const NO_HANDLER_ERROR_CODE: u16 = 302u16;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -59,7 +63,54 @@ pub struct ConnectionSetupRequest {
     pub client_version: Option<String>,
     pub tenant: Option<String>,
     pub labels: HashMap<String, String>,
-    //pub abilities: Option<ClientAbilities>,
+    pub abilities: Option<ClientAbilities>,
+    /// nacos 2.3+ 的能力协商表,不为空时服务端会回复 SetupAckRequest
+    pub ability_table: Option<HashMap<String, bool>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientAbilities {
+    pub remote_ability: ClientRemoteAbility,
+    pub config_ability: ClientConfigAbility,
+    pub naming_ability: ClientNamingAbility,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientRemoteAbility {
+    pub support_remote_connection: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientConfigAbility {
+    pub support_remote_metrics: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientNamingAbility {
+    pub support_delta_push: bool,
+    pub support_remote_metric: bool,
+}
+
+impl ClientAbilities {
+    /// 当前客户端支持的能力
+    pub fn current() -> Self {
+        Self {
+            remote_ability: ClientRemoteAbility {
+                support_remote_connection: true,
+            },
+            config_ability: ClientConfigAbility {
+                support_remote_metrics: false,
+            },
+            naming_ability: ClientNamingAbility {
+                support_delta_push: false,
+                support_remote_metric: false,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -70,6 +121,9 @@ pub struct ServerCheckResponse {
     pub message: Option<String>,
     pub request_id: Option<String>,
     pub connection_id: Option<String>,
+    /// nacos 2.3+ 才有该字段
+    #[serde(default)]
+    pub support_ability_negotiation: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -80,6 +134,49 @@ pub struct ClientDetectionRequest {
     pub headers: HashMap<String, String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SetupAckRequest {
+    pub module: Option<String>,
+    pub request_id: Option<String>,
+    pub headers: HashMap<String, String>,
+
+    pub ability_table: Option<HashMap<String, bool>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectResetRequest {
+    pub module: Option<String>,
+    pub request_id: Option<String>,
+    pub headers: HashMap<String, String>,
+
+    pub server_ip: Option<String>,
+    pub server_port: Option<String>,
+}
+
+impl ConnectResetRequest {
+    /// 服务端指定的切换目标,未指定时返回None
+    pub fn get_server_host(&self) -> Option<HostInfo> {
+        let ip = self
+            .server_ip
+            .as_ref()
+            .map(|e| e.trim())
+            .unwrap_or_default();
+        if ip.is_empty() {
+            return None;
+        }
+        match self
+            .server_port
+            .as_ref()
+            .and_then(|e| e.trim().parse::<u32>().ok())
+        {
+            Some(port) => Some(HostInfo::new(ip, port)),
+            None => Some(HostInfo::parse(ip)),
+        }
+    }
+}
+
 // --- config ---
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -261,7 +358,7 @@ pub struct ServiceInfo {
     pub cache_millis: i64,
     pub hosts: Option<Vec<Instance>>,
     pub last_ref_time: i64,
-    //pub checksum: Option<String>,
+    pub checksum: Option<String>,
     #[serde(rename = "allIPs")]
     pub all_ips: bool,
     pub reach_protection_threshold: bool,
@@ -343,3 +440,21 @@ pub struct NotifySubscriberRequest {
 
     pub service_info: Option<ServiceInfo>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectResetRequest;
+
+    #[test]
+    fn test_connect_reset_server_host() {
+        let request: ConnectResetRequest = serde_json::from_str(
+            r#"{"requestId":"1","serverIp":"192.168.1.2","serverPort":"8848","headers":{}}"#,
+        )
+        .unwrap();
+        let host = request.get_server_host().unwrap();
+        assert_eq!(host.get_grpc_addr(), "192.168.1.2:9848");
+        let request: ConnectResetRequest =
+            serde_json::from_str(r#"{"requestId":"2","headers":{}}"#).unwrap();
+        assert!(request.get_server_host().is_none());
+    }
+}
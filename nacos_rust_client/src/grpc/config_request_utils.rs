@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use tonic::transport::Channel;
 
 use crate::{
@@ -10,7 +12,7 @@ use super::{
     api_model::{
         BaseResponse, ConfigBatchListenRequest, ConfigChangeBatchListenResponse,
         ConfigListenContext, ConfigPublishRequest, ConfigQueryRequest, ConfigQueryResponse,
-        ConfigRemoveRequest,
+        ConfigRemoveRequest, ServerCheckResponse,
     },
     do_timeout_request,
     utils::PayloadUtils,
@@ -19,7 +21,7 @@ use super::{
 pub(crate) struct GrpcConfigRequestUtils;
 
 impl GrpcConfigRequestUtils {
-    pub async fn check_register(channel: Channel) -> anyhow::Result<bool> {
+    pub async fn check_register(channel: Channel, timeout: Duration) -> anyhow::Result<bool> {
         let check_id = format!("__check_register_{}", now_millis());
         let config_key = ConfigKey::new(&check_id, "__check", "");
         let request = ConfigQueryRequest {
@@ -32,7 +34,7 @@ impl GrpcConfigRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ConfigQueryRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("check_register,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -44,8 +46,20 @@ impl GrpcConfigRequestUtils {
         }
     }
 
+    pub async fn server_check(
+        channel: Channel,
+        timeout: Duration,
+    ) -> anyhow::Result<ServerCheckResponse> {
+        let payload = PayloadUtils::build_payload("ServerCheckRequest", "{}".to_owned());
+        let payload = do_timeout_request(channel, payload, timeout).await?;
+        let body_vec = payload.body.unwrap_or_default().value;
+        let response: ServerCheckResponse = serde_json::from_slice(&body_vec)?;
+        Ok(response)
+    }
+
     pub async fn config_query(
         channel: Channel,
+        timeout: Duration,
         request_id: Option<String>,
         config_key: ConfigKey,
     ) -> anyhow::Result<ConfigResponse> {
@@ -59,7 +73,7 @@ impl GrpcConfigRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ConfigQueryRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("config_query,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -77,6 +91,7 @@ impl GrpcConfigRequestUtils {
 
     pub async fn config_publish(
         channel: Channel,
+        timeout: Duration,
         request_id: Option<String>,
         config_key: ConfigKey,
         content: String,
@@ -92,7 +107,7 @@ impl GrpcConfigRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ConfigPublishRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("config_publish,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -109,6 +124,7 @@ impl GrpcConfigRequestUtils {
 
     pub async fn config_remove(
         channel: Channel,
+        timeout: Duration,
         request_id: Option<String>,
         config_key: ConfigKey,
     ) -> anyhow::Result<ConfigResponse> {
@@ -122,7 +138,7 @@ impl GrpcConfigRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ConfigRemoveRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("config_remove,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -139,6 +155,7 @@ impl GrpcConfigRequestUtils {
 
     pub async fn config_change_batch_listen(
         channel: Channel,
+        timeout: Duration,
         request_id: Option<String>,
         listen_items: Vec<(ConfigKey, String)>,
         listen: bool,
@@ -162,7 +179,7 @@ impl GrpcConfigRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ConfigBatchListenRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("config_change_batch_listen,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -12,16 +12,21 @@ use crate::{
             ServiceResult,
         },
         manage::{ConnManage, ConnManageCmd},
+        options::ConnectionOptions,
     },
     grpc::{
-        api_model::{BaseResponse, ConnectionSetupRequest},
+        ability::ServerAbilities,
+        api_model::{BaseResponse, ClientAbilities, ConnectionSetupRequest, SetupAckRequest},
         channel::CloseableChannel,
         utils::PayloadUtils,
     },
 };
 
 use super::{
-    api_model::{ConfigChangeNotifyRequest, NotifySubscriberRequest},
+    api_model::{
+        ClientDetectionRequest, ConfigChangeNotifyRequest, ConnectResetRequest,
+        NotifySubscriberRequest, NO_HANDLER_ERROR_CODE,
+    },
     config_request_utils::GrpcConfigRequestUtils,
     constant::*,
     nacos_proto::{
@@ -48,6 +53,11 @@ pub struct InnerGrpcClient {
     request_id: u64,
     error_time: u8,
     client_info: Arc<ClientInfo>,
+    //主动关闭时不再通知 ConnManage 重连
+    closing: bool,
+    server_abilities: ServerAbilities,
+    request_timeout: Duration,
+    keepalive_interval: Duration,
 }
 
 impl InnerGrpcClient {
@@ -81,9 +91,19 @@ impl InnerGrpcClient {
             request_id: 0,
             error_time: 0,
             client_info,
+            closing: false,
+            server_abilities: Default::default(),
+            request_timeout: Duration::from_millis(3000),
+            keepalive_interval: Duration::from_millis(5000),
         })
     }
 
+    pub(crate) fn set_connection_options(mut self, options: &ConnectionOptions) -> Self {
+        self.request_timeout = options.request_timeout;
+        self.keepalive_interval = options.keepalive_interval;
+        self
+    }
+
     fn next_request_id(&mut self) -> String {
         if self.request_id >= 0x7fff_ffff_ffff_ffff {
             self.request_id = 0;
@@ -125,8 +145,11 @@ impl InnerGrpcClient {
                 }
                 Err(err) => {
                     log::error!("conn_bi_stream error,{:?}", &err);
-                    if let Some(manage_conn) = actor.manage_addr.upgrade() {
-                        manage_conn.do_send(ConnManageCmd::GrpcRequestCheckError { id: actor.id });
+                    if !actor.closing {
+                        if let Some(manage_conn) = actor.manage_addr.upgrade() {
+                            manage_conn
+                                .do_send(ConnManageCmd::GrpcRequestCheckError { id: actor.id });
+                        }
                     }
                     ctx.stop();
                 }
@@ -138,32 +161,64 @@ impl InnerGrpcClient {
 
     fn wait_check_register(&mut self, ctx: &mut Context<Self>) {
         let channel = self.channel.clone();
+        let request_timeout = self.request_timeout;
         async move {
+            let support_negotiation = match GrpcConfigRequestUtils::server_check(
+                channel.clone(),
+                request_timeout,
+            )
+            .await
+            {
+                Ok(res) => res.support_ability_negotiation,
+                Err(err) => {
+                    log::warn!("server_check error,{}", err);
+                    false
+                }
+            };
+            let mut registered = false;
             for _ in 0..100 {
-                match GrpcConfigRequestUtils::check_register(channel.clone()).await {
+                match GrpcConfigRequestUtils::check_register(channel.clone(), request_timeout).await
+                {
                     Ok(r) => {
                         if r {
-                            return true;
+                            registered = true;
+                            break;
                         }
                         tokio::time::sleep(Duration::from_millis(100)).await;
                     }
                     Err(err) => {
                         log::error!("check_register error,{}", err.to_string());
-                        return false;
+                        break;
                     }
                 }
             }
-            return false;
+            (registered, support_negotiation)
         }
         .into_actor(self)
-        .map(|_r, act, _ctx| {
+        .map(|(registered, support_negotiation), act, _ctx| {
             //TODO 如果注册失败，触发重新建立链接
             //act.conn_reader=r;
             act.conn_reader = true;
+            if registered {
+                if let Some(manage_addr) = act.manage_addr.upgrade() {
+                    manage_addr.do_send(ConnManageCmd::GrpcConnected { id: act.id });
+                }
+            }
+            act.server_abilities.support_negotiation |= support_negotiation;
+            act.notify_server_abilities();
         })
         .wait(ctx);
     }
 
+    fn notify_server_abilities(&self) {
+        if let Some(manage_addr) = self.manage_addr.upgrade() {
+            manage_addr.do_send(ConnManageCmd::ServerAbilities {
+                id: self.id,
+                abilities: Arc::new(self.server_abilities.clone()),
+            });
+        }
+    }
+
     fn bi_stream_setup(&mut self, ctx: &mut Context<Self>) {
         let tx = self.stream_sender.clone().unwrap();
         let client_info = self.client_info.clone();
@@ -175,6 +230,11 @@ impl InnerGrpcClient {
             setup_request
                 .labels
                 .insert(LABEL_MODULE.to_owned(), LABEL_MODULE_NAMING.to_owned());
+            setup_request
+                .labels
+                .insert(LABEL_SOURCE.to_owned(), LABEL_SOURCE_SDK.to_owned());
+            setup_request.abilities = Some(ClientAbilities::current());
+            setup_request.ability_table = Some(HashMap::new());
             //setup_request.labels.insert(LABEL_MODULE.to_owned(), LABEL_MODULE_CONFIG.to_owned());
             setup_request.client_version = Some("0.3".to_owned());
             match tx
@@ -208,15 +268,20 @@ impl InnerGrpcClient {
 
     async fn do_config_change_notify(
         channel: Channel,
+        request_timeout: Duration,
         request_id: String,
         manage_addr: &WeakAddr<ConnManage>,
         config_key: ConfigKey,
     ) -> anyhow::Result<()> {
         //debug
         //log::info!( "config change notify:{}#{}#{}", &config_key.data_id, &config_key.group, &config_key.tenant);
-        if let ConfigResponse::ConfigValue(content, md5) =
-            GrpcConfigRequestUtils::config_query(channel, Some(request_id), config_key.clone())
-                .await?
+        if let ConfigResponse::ConfigValue(content, md5) = GrpcConfigRequestUtils::config_query(
+            channel,
+            request_timeout,
+            Some(request_id),
+            config_key.clone(),
+        )
+        .await?
         {
             let msg = ConnCallbackMsg::ConfigChange(config_key, content, md5);
             if let Some(addr) = manage_addr.upgrade() {
@@ -231,10 +296,12 @@ impl InnerGrpcClient {
         ctx: &mut Context<Self>,
         mut receiver_stream: ReceiverStreamType,
     ) {
-        //let addr = ctx.address();
+        let addr = ctx.address();
         let channel = self.channel.clone();
+        let request_timeout = self.request_timeout;
         let tx = self.stream_sender.clone().unwrap();
         let manage_addr = self.manage_addr.clone();
+        let id = self.id;
         async move {
             let mut stream_id = 0u128;
             while let Some(item) = receiver_stream.next().await {
@@ -255,6 +322,7 @@ impl InnerGrpcClient {
                                     stream_id += 1;
                                     Self::do_config_change_notify(
                                         channel.clone(),
+                                        request_timeout,
                                         request_id,
                                         &manage_addr,
                                         config_key,
@@ -298,6 +366,74 @@ impl InnerGrpcClient {
                                     log::error!("NotifySubscriberRequest error {}", e);
                                 }
                             };
+                        } else if t == "SetupAckRequest" {
+                            match serde_json::from_slice::<SetupAckRequest>(&body_vec) {
+                                Ok(request) => {
+                                    let response =
+                                        BaseResponse::build_with_request_id(request.request_id);
+                                    let res_payload = PayloadUtils::build_payload(
+                                        "SetupAckResponse",
+                                        response.to_json_string(),
+                                    );
+                                    tx.send(Some(res_payload)).await.ok();
+                                    addr.do_send(InnerGrpcClientCmd::SetupAck(
+                                        request.ability_table.unwrap_or_default(),
+                                    ));
+                                }
+                                Err(e) => {
+                                    log::error!("SetupAckRequest error {}", e);
+                                }
+                            }
+                        } else if t == "ClientDetectionRequest" {
+                            let request_id =
+                                serde_json::from_slice::<ClientDetectionRequest>(&body_vec)
+                                    .ok()
+                                    .and_then(|e| e.request_id);
+                            let response = BaseResponse::build_with_request_id(request_id);
+                            let res_payload = PayloadUtils::build_payload(
+                                "ClientDetectionResponse",
+                                response.to_json_string(),
+                            );
+                            tx.send(Some(res_payload)).await.ok();
+                        } else if t == "ConnectResetRequest" {
+                            match serde_json::from_slice::<ConnectResetRequest>(&body_vec) {
+                                Ok(request) => {
+                                    let host = request.get_server_host();
+                                    log::info!(
+                                        "receive ConnectResetRequest,target server:{:?}",
+                                        host.as_ref().map(|e| e.get_addr())
+                                    );
+                                    let response =
+                                        BaseResponse::build_with_request_id(request.request_id);
+                                    let res_payload = PayloadUtils::build_payload(
+                                        "ConnectResetResponse",
+                                        response.to_json_string(),
+                                    );
+                                    tx.send(Some(res_payload)).await.ok();
+                                    if let Some(manage_addr) = manage_addr.upgrade() {
+                                        manage_addr
+                                            .do_send(ConnManageCmd::ConnectReset { id, host });
+                                    }
+                                }
+                                Err(e) => {
+                                    log::error!("ConnectResetRequest error {}", e);
+                                }
+                            }
+                        } else {
+                            log::warn!("receive_bi_stream,unknown request type:{}", &t);
+                            let mut response = BaseResponse::build_error_response(
+                                NO_HANDLER_ERROR_CODE,
+                                format!("no handler for {}", &t),
+                            );
+                            response.request_id =
+                                serde_json::from_slice::<serde_json::Value>(&body_vec)
+                                    .ok()
+                                    .and_then(|e| e["requestId"].as_str().map(|v| v.to_owned()));
+                            let res_payload = PayloadUtils::build_payload(
+                                "ErrorResponse",
+                                response.to_json_string(),
+                            );
+                            tx.send(Some(res_payload)).await.ok();
                         }
                     }
                 } else {
@@ -308,8 +444,10 @@ impl InnerGrpcClient {
         }
         .into_actor(self)
         .map(|_, actor, ctx| {
-            if let Some(manage_conn) = actor.manage_addr.upgrade() {
-                manage_conn.do_send(ConnManageCmd::GrpcRequestCheckError { id: actor.id });
+            if !actor.closing {
+                if let Some(manage_conn) = actor.manage_addr.upgrade() {
+                    manage_conn.do_send(ConnManageCmd::GrpcRequestCheckError { id: actor.id });
+                }
             }
             ctx.stop();
         })
@@ -347,7 +485,7 @@ impl InnerGrpcClient {
         }
         .into_actor(self)
         .map(|r, act, _| {
-            if !r {
+            if !r && !act.closing {
                 act.error_time += 1;
                 if act.error_time > 1 {
                     log::warn!("GrpcRequestCheckError");
@@ -371,20 +509,15 @@ impl InnerGrpcClient {
                 namespace_id: request.namespace,
                 group_name: service_info.group_name.clone().unwrap_or_default(),
                 service_name: service_info.name.clone().unwrap_or_default(),
+                clusters: service_info
+                    .clusters
+                    .as_ref()
+                    .filter(|e| !e.is_empty())
+                    .map(|e| e.split(',').map(|v| v.to_owned()).collect()),
             };
-            let hosts = service_info
-                .hosts
-                .unwrap_or_default()
-                .into_iter()
-                .map(|e| Arc::new(GrpcNamingRequestUtils::convert_to_instance(e, &service_key)))
-                .collect();
-            (
-                service_key,
-                ServiceResult {
-                    hosts,
-                    cache_millis: Some(service_info.cache_millis as u64),
-                },
-            )
+            let service_result =
+                GrpcNamingRequestUtils::convert_to_service_result(service_info, &service_key);
+            (service_key, service_result)
         } else {
             (Default::default(), Default::default())
         }
@@ -399,7 +532,7 @@ impl InnerGrpcClient {
     }
 
     pub fn heartbeat(&self, ctx: &mut actix::Context<Self>) {
-        ctx.run_later(Duration::from_millis(5000), |act, ctx| {
+        ctx.run_later(self.keepalive_interval, |act, ctx| {
             act.check_heartbeat(ctx);
             act.heartbeat(ctx);
         });
@@ -423,6 +556,10 @@ pub enum InnerGrpcClientCmd {
     ReceiverStreamItem(Payload),
     Request(Payload, Option<PayloadSenderType>),
     Ping,
+    /// 等待 grace 让处理中的请求完成后关闭连接
+    Close(Duration),
+    /// 服务端通过 SetupAckRequest 下发的能力表
+    SetupAck(HashMap<String, bool>),
 }
 
 pub enum InnerGrpcClientResult {
@@ -436,6 +573,21 @@ impl Handler<InnerGrpcClientCmd> for InnerGrpcClient {
         match msg {
             InnerGrpcClientCmd::ReceiverStreamItem(payload) => Ok(InnerGrpcClientResult::None),
             InnerGrpcClientCmd::Ping => Ok(InnerGrpcClientResult::None),
+            InnerGrpcClientCmd::Close(grace) => {
+                self.closing = true;
+                ctx.run_later(grace, |act, ctx| {
+                    log::info!("InnerGrpcClient close,id:{}", act.id);
+                    act.stream_sender = None;
+                    ctx.stop();
+                });
+                Ok(InnerGrpcClientResult::None)
+            }
+            InnerGrpcClientCmd::SetupAck(ability_table) => {
+                self.server_abilities.support_negotiation = true;
+                self.server_abilities.ability_table = ability_table;
+                self.notify_server_abilities();
+                Ok(InnerGrpcClientResult::None)
+            }
             InnerGrpcClientCmd::Request(payload, sender) => {
                 self.do_request(ctx, payload, sender);
                 Ok(InnerGrpcClientResult::None)
@@ -451,6 +603,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
         let channel = self.channel.clone();
         let manage_addr = self.manage_addr.clone();
         let conn_reader = self.conn_reader;
+        let request_timeout = self.request_timeout;
         let request_id = self.next_request_id();
         let fut = async move {
             if !conn_reader {
@@ -462,6 +615,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
                 ConfigRequest::GetConfig(config_key) => {
                     return GrpcConfigRequestUtils::config_query(
                         channel,
+                        request_timeout,
                         Some(request_id),
                         config_key,
                     )
@@ -470,6 +624,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
                 ConfigRequest::SetConfig(config_key, content) => {
                     let res = GrpcConfigRequestUtils::config_publish(
                         channel.clone(),
+                        request_timeout,
                         Some(request_id),
                         config_key.clone(),
                         content,
@@ -489,6 +644,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
                 ConfigRequest::DeleteConfig(config_key) => {
                     let res = GrpcConfigRequestUtils::config_remove(
                         channel.clone(),
+                        request_timeout,
                         Some(request_id),
                         config_key.clone(),
                     )
@@ -511,6 +667,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
                     //println!("grpc Listen");
                     let res = GrpcConfigRequestUtils::config_change_batch_listen(
                         channel.clone(),
+                        request_timeout,
                         Some(request_id),
                         listen_items,
                         listen,
@@ -520,6 +677,7 @@ impl Handler<ConfigRequest> for InnerGrpcClient {
                         for config_key in keys {
                             Self::do_config_change_notify(
                                 channel.clone(),
+                                request_timeout,
                                 config_key.build_key(),
                                 &manage_addr,
                                 config_key,
@@ -544,6 +702,7 @@ impl Handler<NamingRequest> for InnerGrpcClient {
     fn handle(&mut self, request: NamingRequest, ctx: &mut Self::Context) -> Self::Result {
         let channel = self.channel.clone();
         let conn_reader = self.conn_reader;
+        let request_timeout = self.request_timeout;
         let manage_addr = self.manage_addr.clone();
         let fut = async move {
             if !conn_reader {
@@ -553,22 +712,36 @@ impl Handler<NamingRequest> for InnerGrpcClient {
             }
             match request {
                 NamingRequest::Register(instance) => {
-                    GrpcNamingRequestUtils::instance_register(channel, instance, true).await
+                    GrpcNamingRequestUtils::instance_register(
+                        channel,
+                        request_timeout,
+                        instance,
+                        true,
+                    )
+                    .await
                 }
                 NamingRequest::Unregister(instance) => {
-                    GrpcNamingRequestUtils::instance_register(channel, instance, false).await
+                    GrpcNamingRequestUtils::instance_register(
+                        channel,
+                        request_timeout,
+                        instance,
+                        false,
+                    )
+                    .await
                 }
                 NamingRequest::BatchRegister(instances) => {
-                    GrpcNamingRequestUtils::batch_register(channel, instances).await
+                    GrpcNamingRequestUtils::batch_register(channel, request_timeout, instances)
+                        .await
                 }
                 NamingRequest::Subscribe(service_keys) => {
                     let mut res = Ok(NamingResponse::None);
                     for service_key in service_keys {
                         res = GrpcNamingRequestUtils::subscribe(
                             channel.clone(),
+                            request_timeout,
                             service_key.clone(),
                             true,
-                            Some("".to_owned()),
+                            Some(service_key.get_clusters_str()),
                         )
                         .await;
                         if let Ok(res) = &res {
@@ -587,11 +760,13 @@ impl Handler<NamingRequest> for InnerGrpcClient {
                 NamingRequest::Unsubscribe(service_keys) => {
                     let mut res = Ok(NamingResponse::None);
                     for service_key in service_keys {
+                        let clusters = service_key.get_clusters_str();
                         res = GrpcNamingRequestUtils::subscribe(
                             channel.clone(),
+                            request_timeout,
                             service_key,
                             false,
-                            Some("".to_owned()),
+                            Some(clusters),
                         )
                         .await;
                     }
@@ -606,13 +781,14 @@ impl Handler<NamingRequest> for InnerGrpcClient {
                     };
                     GrpcNamingRequestUtils::query_service(
                         channel,
+                        request_timeout,
                         service_key,
                         clusters,
                         Some(param.healthy_only),
                     )
                     .await
                 }
-                NamingRequest::V1Heartbeat(_) => todo!(),
+                NamingRequest::V1Heartbeat(_) => Err(anyhow::anyhow!("grpc not support")),
             }
             //Ok(NamingResponse::None)
         }
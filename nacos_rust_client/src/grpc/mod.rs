@@ -5,6 +5,7 @@ use tonic::transport::Channel;
 
 use self::nacos_proto::request_client::RequestClient;
 
+pub mod ability;
 pub mod api_model;
 pub mod channel;
 pub mod config_request_utils;
@@ -14,16 +15,15 @@ pub mod nacos_proto;
 pub mod naming_request_utils;
 pub mod utils;
 
-const REQUEST_TIMEOUT: Duration = Duration::from_millis(3000);
-
 pub async fn do_timeout_request(
     channel: Channel,
     payload: nacos_proto::Payload,
+    request_timeout: Duration,
 ) -> anyhow::Result<nacos_proto::Payload> {
     let mut request_client = RequestClient::new(channel);
     //let response = timeout(Duration::from_millis(3000), request_client.request(tonic::Request::new(payload))).await??;
     let response = timeout(
-        REQUEST_TIMEOUT,
+        request_timeout,
         request_client.request(tonic::Request::new(payload)),
     )
     .await??;
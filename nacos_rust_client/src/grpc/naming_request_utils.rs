@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use tonic::transport::Channel;
 
@@ -10,8 +10,9 @@ use crate::{
 
 use super::{
     api_model::{
-        BaseResponse, BatchInstanceRequest, Instance as ApiInstance, ServiceQueryRequest,
-        ServiceQueryResponse, SubscribeServiceRequest, SubscribeServiceResponse,
+        BaseResponse, BatchInstanceRequest, Instance as ApiInstance, ServiceInfo,
+        ServiceQueryRequest, ServiceQueryResponse, SubscribeServiceRequest,
+        SubscribeServiceResponse,
     },
     do_timeout_request,
     utils::PayloadUtils,
@@ -61,11 +62,39 @@ impl GrpcNamingRequestUtils {
         }
     }
 
+    pub(crate) fn convert_to_service_result(
+        service_info: ServiceInfo,
+        service_key: &ServiceInstanceKey,
+    ) -> ServiceResult {
+        let hosts = service_info
+            .hosts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| Arc::new(Self::convert_to_instance(e, service_key)))
+            .collect();
+        ServiceResult {
+            hosts,
+            cache_millis: Some(service_info.cache_millis as u64),
+            protect_threshold: None,
+            metadata: None,
+            clusters: service_info.clusters.filter(|e| !e.is_empty()),
+            checksum: service_info.checksum.filter(|e| !e.is_empty()),
+            last_ref_time: Some(service_info.last_ref_time),
+        }
+    }
+
     pub async fn instance_register(
         channel: Channel,
+        timeout: Duration,
         instance: Instance,
         is_reqister: bool,
     ) -> anyhow::Result<NamingResponse> {
+        //持久化实例需要服务端支持 supportPersistentInstanceByGrpc 能力
+        let request_type = if instance.ephemeral {
+            "InstanceRequest"
+        } else {
+            "PersistentInstanceRequest"
+        };
         let request = InstanceRequest {
             namespace: Some(instance.namespace_id.to_owned()),
             service_name: Some(instance.service_name.to_owned()),
@@ -81,10 +110,10 @@ impl GrpcNamingRequestUtils {
         };
 
         let val = serde_json::to_string(&request).unwrap();
-        let payload = PayloadUtils::build_payload("InstanceRequest", val);
+        let payload = PayloadUtils::build_payload(request_type, val);
         //debug
         //log::info!("instance_register request,{}",&PayloadUtils::get_payload_string(&payload));
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("instance_register,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -101,6 +130,7 @@ impl GrpcNamingRequestUtils {
 
     pub async fn batch_register(
         channel: Channel,
+        timeout: Duration,
         instances: Vec<Instance>,
     ) -> anyhow::Result<NamingResponse> {
         if instances.len() == 0 {
@@ -123,7 +153,7 @@ impl GrpcNamingRequestUtils {
 
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("BatchInstanceRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("batch_register,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -140,6 +170,7 @@ impl GrpcNamingRequestUtils {
 
     pub async fn subscribe(
         channel: Channel,
+        timeout: Duration,
         service_key: ServiceInstanceKey,
         is_subscribe: bool,
         clusters: Option<String>,
@@ -156,7 +187,7 @@ impl GrpcNamingRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("SubscribeServiceRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //debug
         //log::info!("subscribe,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
@@ -166,15 +197,7 @@ impl GrpcNamingRequestUtils {
             return Err(anyhow::anyhow!("response error code"));
         }
         if let Some(service_info) = res.service_info {
-            let hosts = service_info.hosts.unwrap_or_default();
-            let hosts = hosts
-                .into_iter()
-                .map(|e| Arc::new(Self::convert_to_instance(e, &clone_key)))
-                .collect::<Vec<Arc<Instance>>>();
-            let service_result = ServiceResult {
-                cache_millis: Some(service_info.cache_millis as u64),
-                hosts,
-            };
+            let service_result = Self::convert_to_service_result(service_info, &clone_key);
             Ok(NamingResponse::ServiceResult(service_result))
         } else {
             if is_subscribe {
@@ -186,6 +209,7 @@ impl GrpcNamingRequestUtils {
 
     pub async fn query_service(
         channel: Channel,
+        timeout: Duration,
         service_key: ServiceInstanceKey,
         cluster: Option<String>,
         healthy_only: Option<bool>,
@@ -202,7 +226,7 @@ impl GrpcNamingRequestUtils {
         };
         let val = serde_json::to_string(&request).unwrap();
         let payload = PayloadUtils::build_payload("ServiceQueryRequest", val);
-        let payload = do_timeout_request(channel, payload).await?;
+        let payload = do_timeout_request(channel, payload, timeout).await?;
         //log::info!("query_service,{}",&PayloadUtils::get_payload_string(&payload));
         let body_vec = payload.body.unwrap_or_default().value;
         let res: ServiceQueryResponse = serde_json::from_slice(&body_vec)?;
@@ -214,15 +238,7 @@ impl GrpcNamingRequestUtils {
             return Err(anyhow::anyhow!("response error code"));
         }
         if let Some(service_info) = res.service_info {
-            let hosts = service_info.hosts.unwrap_or_default();
-            let hosts = hosts
-                .into_iter()
-                .map(|e| Arc::new(Self::convert_to_instance(e, &clone_key)))
-                .collect::<Vec<Arc<Instance>>>();
-            let service_result = ServiceResult {
-                cache_millis: Some(service_info.cache_millis as u64),
-                hosts,
-            };
+            let service_result = Self::convert_to_service_result(service_info, &clone_key);
             Ok(NamingResponse::ServiceResult(service_result))
         } else {
             log::warn!("subscribe service result is empty");